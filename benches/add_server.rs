@@ -5,7 +5,6 @@ use criterion::{criterion_group, criterion_main, Criterion};
 use ump::channel;
 
 enum Ops {
-  Die,
   Add(i32, i32),
   AddThreaded(i32, i32)
 }
@@ -14,15 +13,9 @@ pub fn criterion_benchmark(c: &mut Criterion) {
   let (server, client) = channel::<Ops, i32, ()>();
 
   let server_thread = thread::spawn(move || {
-    let mut croak = false;
-
-    while croak == false {
-      let (data, rctx) = server.wait();
+    // Keep serving until the client is dropped
+    while let Some((data, rctx)) = server.wait() {
       match data {
-        Ops::Die => {
-          croak = true;
-          rctx.reply(0).unwrap();
-        }
         Ops::Add(a, b) => rctx.reply(a + b).unwrap(),
         Ops::AddThreaded(a, b) => {
           thread::spawn(move || {
@@ -56,8 +49,7 @@ pub fn criterion_benchmark(c: &mut Criterion) {
     })
   });
 
-  let result = client.send(Ops::Die).unwrap();
-  assert_eq!(result, 0);
+  drop(client);
 
   server_thread.join().unwrap();
 }
@@ -0,0 +1,79 @@
+use std::thread;
+
+use criterion::{criterion_group, criterion_main, Criterion};
+
+use ump::channel;
+
+/// Measure the cost of passing requests and replies, without any thread
+/// switches, by having the same thread act as both client and server.
+pub fn same_thread(c: &mut Criterion) {
+  let (server, client) = channel::<u32, u32, ()>();
+
+  let mut n: u32 = 0;
+  c.bench_function("post/reply/wait", |b| {
+    b.iter(|| {
+      n = n.wrapping_add(1);
+      let wrply = client.post(n).unwrap();
+      let (msg, rctx) = server.try_wait().unwrap().unwrap();
+      rctx.reply(msg).unwrap();
+      assert_eq!(wrply.wait().unwrap(), n);
+    })
+  });
+
+  c.bench_function("post/drop", |b| {
+    b.iter(|| {
+      // The request is cancelled, and then skipped by the server.
+      drop(client.post(1).unwrap());
+      assert!(matches!(server.try_wait(), Ok(None)));
+    })
+  });
+}
+
+/// Measure complete round-trips between a client and a server running in
+/// separate threads.
+pub fn round_trip(c: &mut Criterion) {
+  let (server, client) = channel::<u32, u32, ()>();
+
+  let server_thread = thread::spawn(move || {
+    while let Some((n, rctx)) = server.wait() {
+      rctx.reply(n).unwrap();
+    }
+  });
+
+  let mut n: u32 = 0;
+  c.bench_function("send (threads)", |b| {
+    b.iter(|| {
+      n = n.wrapping_add(1);
+      assert_eq!(client.send(n).unwrap(), n);
+    })
+  });
+
+  let mut reusing = client.clone();
+  reusing.set_reuse_slot(true);
+  c.bench_function("send (threads, reused slot)", |b| {
+    b.iter(|| {
+      n = n.wrapping_add(1);
+      assert_eq!(reusing.send(n).unwrap(), n);
+    })
+  });
+  drop(reusing);
+
+  let tokrt = tokio::runtime::Builder::new_current_thread()
+    .build()
+    .unwrap();
+  c.bench_function("asend (thread server)", |b| {
+    b.iter(|| {
+      n = n.wrapping_add(1);
+      let reply = tokrt.block_on(client.asend(n)).unwrap();
+      assert_eq!(reply, n);
+    })
+  });
+
+  drop(client);
+  server_thread.join().unwrap();
+}
+
+criterion_group!(benches, same_thread, round_trip);
+criterion_main!(benches);
+
+// vim: set ft=rust et sw=2 ts=2 sts=2 cinoptions=2 tw=79 :
@@ -25,7 +25,7 @@ fn main() {
 
   let client_blueprint = client.clone();
   let server_thread = thread::spawn(move || loop {
-    let (req, rctx) = server.wait();
+    let (req, rctx) = server.wait().unwrap();
     match req {
       Request::CloneClient => rctx
         .reply(Reply::ClientClone(client_blueprint.clone()))
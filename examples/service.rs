@@ -0,0 +1,65 @@
+use std::thread;
+
+use ump::{channel, Handler};
+
+enum Request {
+  Put(String, u32),
+  Get(String)
+}
+
+#[derive(Debug)]
+enum StoreError {
+  NotFound
+}
+
+// A key/value store, which replies to each request with the value stored for
+// the key, if any.
+struct Store {
+  entries: Vec<(String, u32)>
+}
+
+impl Handler<Request, Option<u32>, StoreError> for Store {
+  fn handle(&mut self, req: Request) -> Result<Option<u32>, StoreError> {
+    match req {
+      Request::Put(key, value) => {
+        let old = match self.entries.iter_mut().find(|(k, _)| *k == key) {
+          Some(entry) => Some(std::mem::replace(&mut entry.1, value)),
+          None => {
+            self.entries.push((key, value));
+            None
+          }
+        };
+        Ok(old)
+      }
+      Request::Get(key) => match self.entries.iter().find(|(k, _)| *k == key) {
+        Some((_, value)) => Ok(Some(*value)),
+        None => Err(StoreError::NotFound)
+      }
+    }
+  }
+}
+
+fn main() {
+  let (server, client) = channel::<Request, Option<u32>, StoreError>();
+  let shutdown = server.shutdown_token();
+
+  // The server runs until it's shut down.
+  let server_thread = thread::spawn(move || {
+    server.serve(Store {
+      entries: Vec::new()
+    })
+  });
+
+  let reply = client.send(Request::Put(String::from("answer"), 42));
+  println!("Put answer: {:?}", reply);
+  let reply = client.send(Request::Get(String::from("answer")));
+  println!("Get answer: {:?}", reply);
+  let reply = client.send(Request::Get(String::from("question")));
+  println!("Get question: {:?}", reply);
+
+  shutdown.shutdown();
+  server_thread.join().unwrap();
+  println!("Server done");
+}
+
+// vim: set ft=rust et sw=2 ts=2 sts=2 cinoptions=2 tw=79 :
@@ -8,7 +8,7 @@ fn main() {
   let server_thread = thread::spawn(move || {
     // Wait for data to arrive from a client
     println!("Server waiting for message ..");
-    let (data, rctx) = server.wait();
+    let (data, rctx) = server.wait().unwrap();
 
     println!("Server received: '{}'", data);
 
@@ -24,7 +24,7 @@ fn main() {
 
   let msg = String::from("Client");
   println!("Client sending '{}'", msg);
-  let reply = client.send(String::from(msg)).unwrap();
+  let reply = client.send(msg).unwrap();
   println!("Client received reply '{}'", reply);
   println!("Client done");
 
@@ -25,7 +25,7 @@ fn main() {
     while count < nclients {
       // Wait for data to arrive from a client
       println!("Server waiting for message ..");
-      let (data, rctx) = server.wait();
+      let (data, rctx) = server.wait().unwrap();
 
       // Move the received data and reply context into a thread to allow other
       // messages to be received while processing this message.
@@ -53,7 +53,7 @@ fn main() {
       let name = format!("Client {}", i + 1);
       let msg = String::from(&name);
       println!("{} sending '{}'", name, msg);
-      let reply = client_clone.send(String::from(msg)).unwrap();
+      let reply = client_clone.send(msg).unwrap();
       println!("{} received reply '{}' -- done", name, reply);
     });
     join_handles.push(client_thread);
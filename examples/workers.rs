@@ -0,0 +1,73 @@
+use std::env;
+use std::thread;
+
+use ump::channel;
+
+// Same as threaded_handler, but rather than launching a new thread for each
+// request the server is cloned and handed to a fixed number of worker
+// threads which all pick up requests from the same queue.
+fn main() {
+  // Get number of client threads to kick off.  Default to eight.
+  let args: Vec<String> = env::args().collect();
+  let nclients = if args.len() > 1 {
+    args[1].parse::<usize>().unwrap()
+  } else {
+    8
+  };
+  let nworkers = 3;
+
+  // Create server and original client
+  let (server, client) = channel::<String, String, ()>();
+
+  // Launch worker threads
+  let mut workers = Vec::new();
+  for id in 0..nworkers {
+    let server = server.clone();
+    let worker_thread = thread::spawn(move || {
+      // Keep looping until all clients are gone.  Each worker stops once it
+      // receives a message telling it to quit.
+      loop {
+        let (data, rctx) = server.wait().unwrap();
+        if data == "quit" {
+          rctx.reply(String::from("bye")).unwrap();
+          break;
+        }
+        println!("Worker {} received: '{}'", id, data);
+        let reply = format!("Hello, {}!", data);
+        rctx.reply(reply).unwrap();
+      }
+      println!("Worker {} done", id);
+    });
+    workers.push(worker_thread);
+  }
+
+  // The workers hold their own clones of the server
+  drop(server);
+
+  let mut join_handles = Vec::new();
+  for i in 0..nclients {
+    let client_clone = client.clone();
+    let client_thread = thread::spawn(move || {
+      let name = format!("Client {}", i + 1);
+      let reply = client_clone.send(name.clone()).unwrap();
+      println!("{} received reply '{}' -- done", name, reply);
+    });
+    join_handles.push(client_thread);
+  }
+
+  for n in join_handles {
+    n.join().unwrap();
+  }
+
+  // Tell each worker to quit; a worker that has quit no longer waits for
+  // messages, so each message goes to a different worker.
+  for _ in 0..nworkers {
+    client.send(String::from("quit")).unwrap();
+  }
+
+  for n in workers {
+    n.join().unwrap();
+  }
+}
+
+// vim: set ft=rust et sw=2 ts=2 sts=2 cinoptions=2 tw=79 :
@@ -0,0 +1,390 @@
+//! Ordering of the nodes waiting in the server queue.
+//!
+//! By default nodes are handed to servers in the order they were pushed.
+//! Queues created with a [`Priority`] policy instead hand out the node with
+//! the highest priority first, optionally letting nodes gain priority the
+//! longer they have been waiting so that low-priority nodes are not starved
+//! by a steady flow of high-priority ones.  Queues created for fair queuing
+//! keep a separate sub-queue for each client, and take turns handing out
+//! nodes from the clients that have nodes waiting, so that a single busy
+//! client can't starve the others.
+
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+/// Nodes that can be put in a [`Backlog`].
+pub(crate) trait Node {
+  /// The node's priority.  Nodes with higher priorities are popped first from
+  /// queues that use a [`Priority`] policy.
+  fn priority(&self) -> i32;
+
+  /// Identifies the client that pushed the node.
+  fn client(&self) -> usize;
+
+  /// Number of nodes the client that pushed the node may have popped in a
+  /// row, when nodes are handed out fairly.
+  fn weight(&self) -> u32;
+}
+
+/// Policy for channels whose servers receive messages in priority order,
+/// rather than in the order they were sent.
+///
+/// Pass it to [`priority_channel`](crate::priority_channel()) to create a
+/// channel using it.  Messages are sent with a priority using
+/// [`Client::send_with_priority()`](crate::Client::send_with_priority()) and
+/// its variants; messages sent by other means have priority `0`.  Messages
+/// with the same priority are received in the order they were sent.
+///
+/// # Example
+/// ```
+/// use std::time::Duration;
+/// use ump::Priority;
+///
+/// // Every 100 ms a message spends in the queue raises its priority by one.
+/// let policy = Priority::new().aging(Duration::from_millis(100));
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct Priority {
+  aging: Option<Duration>
+}
+
+impl Priority {
+  /// Create a policy which strictly orders messages by their priority.
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Let messages gain priority while waiting in the queue, so that messages
+  /// with a low priority are eventually received, even if high-priority
+  /// messages keep arriving.
+  ///
+  /// For every `interval` a message has been waiting in the queue it is
+  /// treated as if its priority was one higher.
+  ///
+  /// # Panics
+  /// Panics if `interval` is zero.
+  pub fn aging(mut self, interval: Duration) -> Self {
+    assert!(!interval.is_zero(), "Aging interval must be non-zero");
+    self.aging = Some(interval);
+    self
+  }
+}
+
+
+/// Nodes waiting to be popped off the server queue.
+pub(crate) enum Backlog<I> {
+  Fifo(VecDeque<I>),
+  Priority(PrioQueue<I>),
+  Fair(FairQueue<I>)
+}
+
+impl<I> Backlog<I> {
+  /// Create a backlog which returns nodes in the order they were pushed.
+  pub(crate) fn fifo() -> Self {
+    Backlog::Fifo(VecDeque::new())
+  }
+
+  /// Create a backlog which returns nodes in priority order.
+  pub(crate) fn priority(policy: Priority) -> Self {
+    Backlog::Priority(PrioQueue {
+      heap: BinaryHeap::new(),
+      aging: policy.aging,
+      epoch: Instant::now(),
+      seq: 0
+    })
+  }
+
+  /// Create a backlog which takes turns returning nodes pushed by different
+  /// clients.
+  pub(crate) fn fair() -> Self {
+    Backlog::Fair(FairQueue {
+      clients: HashMap::new(),
+      turns: VecDeque::new(),
+      len: 0
+    })
+  }
+
+  /// Remove the node that should be handed to a server next.
+  pub(crate) fn pop(&mut self) -> Option<I> {
+    match self {
+      Backlog::Fifo(q) => q.pop_front(),
+      Backlog::Priority(q) => q.heap.pop().map(|entry| entry.item),
+      Backlog::Fair(q) => q.pop()
+    }
+  }
+
+  /// Remove the first node, in the order nodes would be popped, for which
+  /// `pred` returns `true`.  The remaining nodes are left in their order.
+  ///
+  /// `pred` is called before any changes are made to the backlog, so a
+  /// panicking predicate leaves it intact.
+  pub(crate) fn pop_matching<F>(&mut self, mut pred: F) -> Option<I>
+  where
+    F: FnMut(&I) -> bool
+  {
+    match self {
+      Backlog::Fifo(q) => {
+        let idx = q.iter().position(pred)?;
+        q.remove(idx)
+      }
+      Backlog::Priority(q) => {
+        let seq = q.heap.iter().filter(|entry| pred(&entry.item)).max()?.seq;
+        let mut entries = std::mem::take(&mut q.heap).into_vec();
+        // Unwrap is okay; the sequence number was just found in the heap.
+        let idx = entries.iter().position(|entry| entry.seq == seq).unwrap();
+        let entry = entries.swap_remove(idx);
+        q.heap = BinaryHeap::from(entries);
+        Some(entry.item)
+      }
+      Backlog::Fair(q) => q.pop_matching(pred)
+    }
+  }
+
+  pub(crate) fn len(&self) -> usize {
+    match self {
+      Backlog::Fifo(q) => q.len(),
+      Backlog::Priority(q) => q.heap.len(),
+      Backlog::Fair(q) => q.len
+    }
+  }
+
+  pub(crate) fn is_empty(&self) -> bool {
+    self.len() == 0
+  }
+
+  /// Remove all nodes, returning them in a backlog of their own.
+  pub(crate) fn take(&mut self) -> Self {
+    match self {
+      Backlog::Fifo(q) => Backlog::Fifo(std::mem::take(q)),
+      Backlog::Priority(q) => Backlog::Priority(PrioQueue {
+        heap: std::mem::take(&mut q.heap),
+        aging: q.aging,
+        epoch: q.epoch,
+        seq: q.seq
+      }),
+      Backlog::Fair(q) => Backlog::Fair(FairQueue {
+        clients: std::mem::take(&mut q.clients),
+        turns: std::mem::take(&mut q.turns),
+        len: std::mem::replace(&mut q.len, 0)
+      })
+    }
+  }
+}
+
+impl<I: Node> Backlog<I> {
+  pub(crate) fn push(&mut self, item: I) {
+    match self {
+      Backlog::Fifo(q) => q.push_back(item),
+      Backlog::Priority(q) => q.push(item),
+      Backlog::Fair(q) => q.push(item)
+    }
+  }
+}
+
+
+pub(crate) struct PrioQueue<I> {
+  heap: BinaryHeap<Entry<I>>,
+
+  /// Time it takes for a waiting node to gain one priority level.
+  aging: Option<Duration>,
+
+  /// Point in time node ages are measured from.
+  epoch: Instant,
+
+  /// Sequence number of the next node, used to keep nodes of equal rank in
+  /// the order they were pushed.
+  seq: u64
+}
+
+impl<I: Node> PrioQueue<I> {
+  fn push(&mut self, item: I) {
+    self.push_at(item, Instant::now());
+  }
+
+  /// Push a node, which ages from `now` on.
+  fn push_at(&mut self, item: I, now: Instant) {
+    let prio = i128::from(item.priority());
+
+    // With aging, a node's effective priority at time `t` is
+    // `prio + (t - pushed) / aging`.  Since `t` is the same for all nodes
+    // when they are compared, nodes can be ranked by `prio * aging - pushed`
+    // once and for all when they are pushed.
+    let rank = match self.aging {
+      Some(aging) => {
+        let pushed = now.duration_since(self.epoch).as_nanos() as i128;
+        prio * aging.as_nanos() as i128 - pushed
+      }
+      None => prio
+    };
+
+    let seq = self.seq;
+    self.seq += 1;
+    self.heap.push(Entry { rank, seq, item });
+  }
+}
+
+
+pub(crate) struct FairQueue<I> {
+  /// Nodes waiting in the queue, for each client that has any.
+  clients: HashMap<usize, SubQueue<I>>,
+
+  /// Clients with nodes waiting, in the order they get their turns.  The
+  /// client at the front is the one whose turn it is.
+  turns: VecDeque<usize>,
+
+  /// Total number of nodes in the queue.
+  len: usize
+}
+
+struct SubQueue<I> {
+  q: VecDeque<I>,
+
+  /// Number of nodes the client may have popped during each turn.
+  weight: u32,
+
+  /// Number of nodes popped during the client's current turn.
+  popped: u32
+}
+
+impl<I> FairQueue<I> {
+  fn pop(&mut self) -> Option<I> {
+    let client = *self.turns.front()?;
+    // Unwrap is okay; clients are only in the list of turns while they have
+    // a sub-queue.
+    let sub = self.clients.get_mut(&client).unwrap();
+    let item = sub.q.pop_front()?;
+    self.len -= 1;
+    sub.popped += 1;
+
+    if sub.q.is_empty() {
+      self.clients.remove(&client);
+      self.turns.pop_front();
+    } else if sub.popped >= sub.weight {
+      sub.popped = 0;
+      self.turns.rotate_left(1);
+    }
+    Some(item)
+  }
+
+  /// Remove the first node for which `pred` returns `true`, looking through
+  /// the clients' sub-queues in the order of their turns.  Turns are left as
+  /// they are, unless the client's sub-queue becomes empty.
+  fn pop_matching<F>(&mut self, mut pred: F) -> Option<I>
+  where
+    F: FnMut(&I) -> bool
+  {
+    for pos in 0..self.turns.len() {
+      let client = self.turns[pos];
+      let sub = self.clients.get_mut(&client).unwrap();
+      if let Some(idx) = sub.q.iter().position(&mut pred) {
+        let item = sub.q.remove(idx);
+        self.len -= 1;
+        if sub.q.is_empty() {
+          self.clients.remove(&client);
+          self.turns.remove(pos);
+        }
+        return item;
+      }
+    }
+    None
+  }
+}
+
+impl<I: Node> FairQueue<I> {
+  fn push(&mut self, item: I) {
+    let client = item.client();
+    let turns = &mut self.turns;
+    let sub = self.clients.entry(client).or_insert_with(|| {
+      turns.push_back(client);
+      SubQueue {
+        q: VecDeque::new(),
+        weight: 1,
+        popped: 0
+      }
+    });
+    sub.weight = item.weight();
+    sub.q.push_back(item);
+    self.len += 1;
+  }
+}
+
+
+struct Entry<I> {
+  rank: i128,
+  seq: u64,
+  item: I
+}
+
+impl<I> Ord for Entry<I> {
+  /// Higher ranks come first; nodes of equal rank are ordered by age.
+  fn cmp(&self, other: &Self) -> Ordering {
+    self
+      .rank
+      .cmp(&other.rank)
+      .then_with(|| other.seq.cmp(&self.seq))
+  }
+}
+
+impl<I> PartialOrd for Entry<I> {
+  fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+    Some(self.cmp(other))
+  }
+}
+
+impl<I> PartialEq for Entry<I> {
+  fn eq(&self, other: &Self) -> bool {
+    self.cmp(other) == Ordering::Equal
+  }
+}
+
+impl<I> Eq for Entry<I> {}
+
+
+#[cfg(test)]
+mod tests {
+  use std::time::Duration;
+
+  use super::{Backlog, Node, Priority};
+
+  impl Node for (&'static str, i32) {
+    fn priority(&self) -> i32 {
+      self.1
+    }
+
+    fn client(&self) -> usize {
+      0
+    }
+
+    fn weight(&self) -> u32 {
+      1
+    }
+  }
+
+
+  #[test]
+  fn aging_outranks_newer_nodes() {
+    let policy = Priority::new().aging(Duration::from_millis(10));
+    let mut backlog = Backlog::priority(policy);
+    let q = match &mut backlog {
+      Backlog::Priority(q) => q,
+      _ => unreachable!()
+    };
+
+    // Having waited for 100 ms, "low" ranks as if its priority was 10, and
+    // comes before the newer node of that priority.
+    let epoch = q.epoch;
+    q.push_at(("low", 0), epoch);
+    let later = epoch + Duration::from_millis(100);
+    q.push_at(("medium", 5), later);
+    q.push_at(("high", 50), later);
+    q.push_at(("even", 10), later);
+
+    let order: Vec<_> = std::iter::from_fn(|| backlog.pop())
+      .map(|(msg, _)| msg)
+      .collect();
+    assert_eq!(order, vec!["high", "low", "even", "medium"]);
+  }
+}
+
+// vim: set ft=rust et sw=2 ts=2 sts=2 cinoptions=2 tw=79 :
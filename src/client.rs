@@ -1,10 +1,15 @@
-use std::sync::Weak;
-
-use sigq::Queue as NotifyQueue;
+use std::collections::VecDeque;
+use std::future::{Future, IntoFuture};
+use std::marker::PhantomData;
+use std::pin::Pin;
+use std::sync::{Mutex, Weak};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
 
 use crate::err::Error;
-use crate::rctx::InnerReplyContext;
+use crate::rctx::{InnerReplyContext, WaitReplyFuture};
 use crate::server::ServerQueueNode;
+use crate::srvq::{NotifyQueue, PushError};
 
 /// Representation of a clonable client object.
 ///
@@ -13,18 +18,31 @@ use crate::server::ServerQueueNode;
 /// client is created.  ("Independent" here meaning that it is still tied to
 /// the same server object, but the new client can be passed to a separate
 /// thread and can independently make calls to the server).
-pub struct Client<S, R, E> {
+pub struct Client<S, R, E, P = ()> {
   /// Weak reference to server queue.
   ///
   /// The server context holds the only strong reference to the queue.  This
   /// allows the clients to detect when the server has terminated.
-  pub(crate) srvq: Weak<NotifyQueue<ServerQueueNode<S, R, E>>>
+  pub(crate) srvq: Weak<NotifyQueue<ServerQueueNode<S, R, E, P>>>,
+
+  /// Identifies the client in the server queue.
+  pub(crate) id: usize,
+
+  /// Number of messages received from the client per turn by servers of
+  /// [fair](crate::fair_channel()) channels.
+  pub(crate) weight: u32,
+
+  /// Reply context kept for reuse by the next request, if the client
+  /// [reuses reply slots](Client::set_reuse_slot()).
+  pub(crate) cache: Option<Mutex<Option<InnerReplyContext<R, E, P>>>>
 }
 
-impl<S, R, E> Client<S, R, E>
+impl<S, R, E, P> Client<S, R, E, P>
 where
+  S: 'static + Send,
   R: 'static + Send,
-  E: 'static + Send
+  E: 'static + Send,
+  P: 'static + Send
 {
   /// Send a message to the server, wait for a reply, and return the reply.
   ///
@@ -42,8 +60,14 @@ where
   /// On success the function will return `Ok(msg)`.
   ///
   /// If the linked server object has been released, or is released while the
-  /// message is in the server's queue, `Err(Error:ServerDisappeared)` will be
-  /// returned.
+  /// message is in the server's queue, `Err(Error:ServerDisappeared(msg))`
+  /// will be returned, where `msg` is the message that was passed in `out`.
+  ///
+  /// If the server has been [closed](crate::Server::close()),
+  /// `Err(Error::ServerClosed(msg))` will be returned.
+  ///
+  /// If the server's queue is [bounded](crate::channel_bounded()) and full,
+  /// this call blocks until there's space for the message.
   ///
   /// If the server never replied to the message and the reply context was
   /// dropped `Err(Error::NoReply)` will be returned.
@@ -51,61 +75,801 @@ where
   /// If an application specific error occurs it will be returned as a
   /// `Err(Error::App(E))`, where `E` is the error type used when creating the
   /// [`channel`](crate::channel).
-  pub fn send(&self, out: S) -> Result<R, Error<E>> {
-    // Make sure the server still lives; Weak -> Arc
+  pub fn send(&self, out: S) -> Result<R, Error<E, S>> {
+    let rctx = self.enqueue(out, 0, None)?;
+
+    let reply = rctx.get();
+    self.recycle(rctx);
+    Ok(reply?)
+  }
+
+  /// Same as [`Client::send()`], but if the server's queue is
+  /// [bounded](crate::channel_bounded()) and full, don't wait for space to
+  /// become available.
+  ///
+  /// # Return
+  /// If the queue is full, `Err(Error::QueueFull(msg))` will be returned
+  /// immediately, where `msg` is the message that was passed in `out`.  Other
+  /// return values are the same as for [`Client::send()`].
+  pub fn try_send(&self, out: S) -> Result<R, Error<E, S>> {
+    let rctx = self.try_enqueue(out)?;
+
+    let reply = rctx.get();
+    self.recycle(rctx);
+    Ok(reply?)
+  }
+
+  /// Same as [`Client::send()`], but give up waiting for a reply after
+  /// `timeout` has elapsed.
+  ///
+  /// # Return
+  /// If the server has not replied within `timeout`, `Err(Error::Timeout)`
+  /// will be returned.  If the server's queue is
+  /// [bounded](crate::channel_bounded()) and remained full for the entire
+  /// duration, `Err(Error::QueueFull(msg))` will be returned.  Other return
+  /// values are the same as for [`Client::send()`].
+  ///
+  /// # Semantics
+  /// Once the call has timed out the request is marked as abandoned.  If the
+  /// server replies to it later the reply is discarded.
+  pub fn send_timeout(
+    &self,
+    out: S,
+    timeout: Duration
+  ) -> Result<R, Error<E, S>> {
+    match Instant::now().checked_add(timeout) {
+      Some(deadline) => self.send_deadline(out, deadline),
+      None => self.send(out)
+    }
+  }
+
+  /// Same as [`Client::send_timeout()`], but give up waiting for a reply at
+  /// a specific point in time rather than after a duration.
+  pub fn send_deadline(
+    &self,
+    out: S,
+    deadline: Instant
+  ) -> Result<R, Error<E, S>> {
+    let rctx = self.enqueue(out, 0, Some(deadline))?;
+
+    let reply = rctx.get_deadline(deadline);
+    self.recycle(rctx);
+    Ok(reply?)
+  }
+
+  /// Send a message to the server, but don't wait for the reply.  Instead
+  /// return a [`WaitReply`] handle which can be used to collect the reply
+  /// later.
+  ///
+  /// This allows a single thread to send requests to several servers (or
+  /// several requests to the same server) and then collect the replies.
+  ///
+  /// If the [`WaitReply`] is dropped before the reply has been collected the
+  /// request is cancelled, just like an abandoned [`Client::asend()`].
+  ///
+  /// # Example
+  /// ```
+  /// use std::thread;
+  /// use ump::channel;
+  ///
+  /// let (server, client) = channel::<u32, u32, ()>();
+  /// let server_thread = thread::spawn(move || {
+  ///   for _ in 0..2 {
+  ///     let (n, rctx) = server.wait().unwrap();
+  ///     rctx.reply(n * 2).unwrap();
+  ///   }
+  /// });
+  /// let first = client.post(1).unwrap();
+  /// let second = client.post(2).unwrap();
+  /// assert_eq!(second.wait().unwrap(), 4);
+  /// assert_eq!(first.wait().unwrap(), 2);
+  /// server_thread.join().unwrap();
+  /// ```
+  ///
+  /// # Return
+  /// If the linked server object has been released
+  /// `Err(Error:ServerDisappeared(msg))` will be returned, where `msg` is the
+  /// message that was passed in `out`.  If the server has been
+  /// [closed](crate::Server::close()) `Err(Error::ServerClosed(msg))` is
+  /// returned instead.  Errors that occur after the message has been queued
+  /// are returned by the [`WaitReply`].
+  ///
+  /// If the server's queue is [bounded](crate::channel_bounded()) and full,
+  /// this call blocks until there's space for the message.
+  pub fn post(&self, out: S) -> Result<WaitReply<S, R, E, P>, Error<E, S>> {
+    let rctx = self.enqueue(out, 0, None)?;
+
+    Ok(WaitReply {
+      rctx: Some(rctx),
+      _marker: PhantomData
+    })
+  }
+
+  /// Same as [`Client::send()`], but send the message with priority `prio`.
+  ///
+  /// On channels created using
+  /// [`priority_channel`](crate::priority_channel()) the server receives
+  /// messages with higher priorities before messages with lower priorities,
+  /// even if they were sent later.  Messages sent without an explicit
+  /// priority have priority `0`, so urgent messages should use a positive
+  /// priority and background work a negative one.  Other channels receive
+  /// messages in the order they were sent, and ignore the priority.
+  ///
+  /// Return values are the same as for [`Client::send()`].
+  pub fn send_with_priority(
+    &self,
+    out: S,
+    prio: i32
+  ) -> Result<R, Error<E, S>> {
+    let rctx = self.enqueue(out, prio, None)?;
+
+    let reply = rctx.get();
+    self.recycle(rctx);
+    Ok(reply?)
+  }
+
+  /// Same as [`Client::post()`], but send the message with priority `prio`.
+  /// See [`Client::send_with_priority()`].
+  pub fn post_with_priority(
+    &self,
+    out: S,
+    prio: i32
+  ) -> Result<WaitReply<S, R, E, P>, Error<E, S>> {
+    let rctx = self.enqueue(out, prio, None)?;
+
+    Ok(WaitReply {
+      rctx: Some(rctx),
+      _marker: PhantomData
+    })
+  }
+
+  /// Same as [`Client::send()`], but call `f` for each progress update the
+  /// server sends, using
+  /// [`ReplyContext::progress()`](crate::ReplyContext::progress()),
+  /// while waiting for the reply.
+  ///
+  /// The updates are passed to `f` in the order they were sent, on the
+  /// calling thread, before the reply is returned.
+  ///
+  /// Return values are the same as for [`Client::send()`].
+  pub fn send_with_progress<F>(
+    &self,
+    out: S,
+    mut f: F
+  ) -> Result<R, Error<E, S>>
+  where
+    F: FnMut(P)
+  {
+    let mut wrply = self.post(out)?;
+    while let Some(progress) = wrply.wait_progress() {
+      f(progress);
+    }
+    wrply.wait()
+  }
+
+  /// Same as [`Client::send_with_progress()`] but for use in `async`
+  /// contexts.
+  pub async fn asend_with_progress<F>(
+    &self,
+    out: S,
+    mut f: F
+  ) -> Result<R, Error<E, S>>
+  where
+    F: FnMut(P)
+  {
+    let rctx = self.aenqueue(out, 0, None).await?;
+
+    // Let the reply handle cancel the request if the future is dropped.
+    let mut wrply = WaitReply {
+      rctx: Some(rctx),
+      _marker: PhantomData
+    };
+    while let Some(progress) = wrply.async_progress().await {
+      f(progress);
+    }
+    wrply.await
+  }
+
+  /// Same as [`Client::send()`] but for use in `async` contexts.
+  pub async fn asend(&self, out: S) -> Result<R, Error<E, S>> {
+    let rctx = self.aenqueue(out, 0, None).await?;
+
+    let result = rctx.aget().await;
+    self.recycle(rctx);
+
+    Ok(result?)
+  }
+
+  /// Same as [`Client::send_with_priority()`] but for use in `async`
+  /// contexts.
+  pub async fn asend_with_priority(
+    &self,
+    out: S,
+    prio: i32
+  ) -> Result<R, Error<E, S>> {
+    let rctx = self.aenqueue(out, prio, None).await?;
+
+    let result = rctx.aget().await;
+    self.recycle(rctx);
+
+    Ok(result?)
+  }
+
+  /// Same as [`Client::send_timeout()`] but for use in `async` contexts.
+  pub async fn asend_timeout(
+    &self,
+    out: S,
+    timeout: Duration
+  ) -> Result<R, Error<E, S>> {
+    match Instant::now().checked_add(timeout) {
+      Some(deadline) => self.asend_deadline(out, deadline).await,
+      None => self.asend(out).await
+    }
+  }
+
+  /// Same as [`Client::send_deadline()`] but for use in `async` contexts.
+  pub async fn asend_deadline(
+    &self,
+    out: S,
+    deadline: Instant
+  ) -> Result<R, Error<E, S>> {
+    let rctx = self.aenqueue(out, 0, Some(deadline)).await?;
+
+    let result = rctx.aget_deadline(deadline).await;
+    self.recycle(rctx);
+
+    Ok(result?)
+  }
+
+  /// Send a message to the server, and receive the reply as a stream of
+  /// items.
+  ///
+  /// This allows a server to return a large result in chunks, as they become
+  /// available, rather than all at once.  The server receives the message as
+  /// an [`Incoming::Stream`](crate::Incoming::Stream) and sends the items
+  /// using the [`StreamReplyContext`](crate::StreamReplyContext) it comes
+  /// with.  Servers receiving the message using
+  /// [`Server::wait()`](crate::Server::wait()) get an ordinary reply context,
+  /// whose reply is returned as the only item of the stream.
+  ///
+  /// # Example
+  /// ```
+  /// use std::thread;
+  /// use ump::{channel, Incoming};
+  ///
+  /// let (server, client) = channel::<u32, u32, ()>();
+  /// let server_thread = thread::spawn(move || {
+  ///   if let Some(Incoming::Stream(n, srctx)) = server.wait_incoming() {
+  ///     for i in 0..n {
+  ///       srctx.send_item(i).unwrap();
+  ///     }
+  ///     srctx.finish().unwrap();
+  ///   }
+  /// });
+  /// let stream = client.send_stream(4).unwrap();
+  /// let items: Result<Vec<u32>, _> = stream.into_iter().collect();
+  /// assert_eq!(items.unwrap(), vec![0, 1, 2, 3]);
+  /// server_thread.join().unwrap();
+  /// ```
+  ///
+  /// # Return
+  /// If the message could not be queued, the errors are the same as for
+  /// [`Client::post()`].  Otherwise a [`ReplyStream`] is returned, which
+  /// yields the items as they arrive.  The stream ends after the last item,
+  /// or after an error; errors that occur once the message has been queued
+  /// are returned by the stream.
+  pub fn send_stream(
+    &self,
+    out: S
+  ) -> Result<ReplyStream<S, R, E, P>, Error<E, S>> {
+    let rctx = InnerReplyContext::new();
+
+    self.push(ServerQueueNode::stream(out, rctx.clone()), None)?;
+
+    Ok(ReplyStream {
+      rctx,
+      _marker: PhantomData
+    })
+  }
+
+  /// Same as [`Client::send_stream()`] but for use in `async` contexts.
+  pub async fn asend_stream(
+    &self,
+    out: S
+  ) -> Result<ReplyStream<S, R, E, P>, Error<E, S>> {
+    let rctx = InnerReplyContext::new();
+
+    self
+      .apush(ServerQueueNode::stream(out, rctx.clone()), None)
+      .await?;
+
+    Ok(ReplyStream {
+      rctx,
+      _marker: PhantomData
+    })
+  }
+
+  /// Same as [`Client::send_stream()`], but buffer at most `cap` items that
+  /// the client hasn't picked up yet.
+  ///
+  /// Once the buffer is full, the server's
+  /// [`send_item()`](crate::StreamReplyContext::send_item()) blocks until the
+  /// client has picked up an item, so a server can't get further ahead of the
+  /// client than that.
+  ///
+  /// # Example
+  /// ```
+  /// use std::thread;
+  /// use ump::{channel, Incoming};
+  ///
+  /// let (server, client) = channel::<u32, u32, ()>();
+  /// let server_thread = thread::spawn(move || {
+  ///   if let Some(Incoming::Stream(n, srctx)) = server.wait_incoming() {
+  ///     for i in 0..n {
+  ///       // Blocks while two items are waiting to be picked up
+  ///       srctx.send_item(i).unwrap();
+  ///     }
+  ///     srctx.finish().unwrap();
+  ///   }
+  /// });
+  /// let stream = client.send_stream_bounded(100, 2).unwrap();
+  /// assert_eq!(stream.into_iter().map(Result::unwrap).sum::<u32>(), 4950);
+  /// server_thread.join().unwrap();
+  /// ```
+  ///
+  /// # Panics
+  /// Panics if `cap` is zero.
+  pub fn send_stream_bounded(
+    &self,
+    out: S,
+    cap: usize
+  ) -> Result<ReplyStream<S, R, E, P>, Error<E, S>> {
+    assert!(cap > 0, "Stream capacity must be non-zero");
+    let rctx = InnerReplyContext::bounded(cap);
+
+    self.push(ServerQueueNode::stream(out, rctx.clone()), None)?;
+
+    Ok(ReplyStream {
+      rctx,
+      _marker: PhantomData
+    })
+  }
+
+  /// Same as [`Client::send_stream_bounded()`] but for use in `async`
+  /// contexts.
+  ///
+  /// # Panics
+  /// Panics if `cap` is zero.
+  pub async fn asend_stream_bounded(
+    &self,
+    out: S,
+    cap: usize
+  ) -> Result<ReplyStream<S, R, E, P>, Error<E, S>> {
+    assert!(cap > 0, "Stream capacity must be non-zero");
+    let rctx = InnerReplyContext::bounded(cap);
+
+    self
+      .apush(ServerQueueNode::stream(out, rctx.clone()), None)
+      .await?;
+
+    Ok(ReplyStream {
+      rctx,
+      _marker: PhantomData
+    })
+  }
+
+  /// Send a batch of messages to the server, wait for all of the replies,
+  /// and return them in the order the messages were sent.
+  ///
+  /// All the messages are put on the server's queue at once, rather than one
+  /// round-trip at a time, so the server can work its way through them
+  /// without waiting for the client in between.  The server receives them as
+  /// ordinary requests, one after the other, or all at once using
+  /// [`Server::wait_batch()`](crate::Server::wait_batch()).
+  ///
+  /// # Example
+  /// ```
+  /// use std::thread;
+  /// use ump::channel;
+  ///
+  /// let (server, client) = channel::<u32, u32, ()>();
+  /// let server_thread = thread::spawn(move || {
+  ///   while let Some((n, rctx)) = server.wait() {
+  ///     rctx.reply(n * n).unwrap();
+  ///   }
+  /// });
+  /// let replies: Result<Vec<u32>, _> =
+  ///   client.send_many(1..=4).into_iter().collect();
+  /// assert_eq!(replies.unwrap(), vec![1, 4, 9, 16]);
+  /// drop(client);
+  /// server_thread.join().unwrap();
+  /// ```
+  ///
+  /// # Return
+  /// Returns one result per message, which is the same as [`Client::send()`]
+  /// would have returned for it.
+  ///
+  /// If the server's queue is [bounded](crate::channel_bounded()), messages
+  /// are queued as space becomes available, and messages from other clients
+  /// may end up between them.  If the server is closed or released before
+  /// all of the messages have been queued, the ones that weren't queued
+  /// fail, and are handed back in their errors.
+  pub fn send_many<I>(&self, msgs: I) -> Vec<Result<R, Error<E, S>>>
+  where
+    I: IntoIterator<Item = S>
+  {
+    let (nodes, rctxs) = self.batch(msgs);
+    let failed = self.push_many(nodes);
+
+    let nqueued = rctxs.len() - failed.len();
+    let mut replies = Vec::with_capacity(rctxs.len());
+    for rctx in &rctxs[..nqueued] {
+      replies.push(rctx.get().map_err(Error::from));
+    }
+    replies.extend(failed.into_iter().map(Err));
+    replies
+  }
+
+  /// Same as [`Client::send_many()`] but for use in `async` contexts.
+  ///
+  /// If the returned future is dropped before all of the replies have
+  /// arrived, the outstanding requests are cancelled.
+  pub async fn asend_many<I>(&self, msgs: I) -> Vec<Result<R, Error<E, S>>>
+  where
+    I: IntoIterator<Item = S>
+  {
+    let (nodes, rctxs) = self.batch(msgs);
+
+    // Set up the futures waiting for the replies before queueing the
+    // messages, so that all of the requests are cancelled if this future is
+    // dropped.
+    let waiting: Vec<_> = rctxs.iter().map(InnerReplyContext::aget).collect();
+    let failed = self.apush_many(nodes).await;
+
+    let nqueued = waiting.len() - failed.len();
+    let mut replies = Vec::with_capacity(waiting.len());
+    for wait in waiting.into_iter().take(nqueued) {
+      replies.push(wait.await.map_err(Error::from));
+    }
+    replies.extend(failed.into_iter().map(Err));
+    replies
+  }
+
+  /// Send a one-way notification to the server, without waiting for any
+  /// reply.
+  ///
+  /// The server receives notifications in the same queue as requests, so
+  /// notifications and requests sent from the same client are received in the
+  /// order they were sent.  Servers using
+  /// [`Server::wait()`](crate::Server::wait()) get a reply context that is
+  /// already cancelled, so a reply is handed back to the server; use
+  /// [`Server::wait_incoming()`](crate::Server::wait_incoming()) to tell
+  /// notifications apart from requests.
+  ///
+  /// # Example
+  /// ```
+  /// use std::thread;
+  /// use ump::{channel, Incoming};
+  ///
+  /// let (server, client) = channel::<String, usize, ()>();
+  /// let server_thread = thread::spawn(move || {
+  ///   let mut log = Vec::new();
+  ///   while let Some(incoming) = server.wait_incoming() {
+  ///     match incoming {
+  ///       Incoming::Notify(line) => log.push(line),
+  ///       Incoming::Request(_, rctx) => rctx.reply(log.len()).unwrap(),
+  ///       Incoming::Stream(_, srctx) => {
+  ///         // Streamed replies aren't supported.  Dropping the reply
+  ///         // context tells the client that no reply is coming.
+  ///         drop(srctx)
+  ///       }
+  ///     }
+  ///   }
+  /// });
+  /// client.notify(String::from("first")).unwrap();
+  /// client.notify(String::from("second")).unwrap();
+  /// assert_eq!(client.send(String::from("count")).unwrap(), 2);
+  /// drop(client);
+  /// server_thread.join().unwrap();
+  /// ```
+  ///
+  /// # Return
+  /// On success the function returns `Ok(())` once the notification has been
+  /// queued; this does not mean that the server has received it.
+  ///
+  /// If the linked server object has been released
+  /// `Err(Error:ServerDisappeared(msg))` will be returned, and if it has been
+  /// [closed](crate::Server::close()) `Err(Error::ServerClosed(msg))` will be
+  /// returned, where `msg` is the message that was passed in `out`.
+  ///
+  /// If the server's queue is [bounded](crate::channel_bounded()) and full,
+  /// this call blocks until there's space for the message.
+  pub fn notify(&self, out: S) -> Result<(), Error<E, S>> {
+    self.push(ServerQueueNode::notification(out), None)
+  }
+
+  /// Same as [`Client::notify()`] but for use in `async` contexts.
+  pub async fn anotify(&self, out: S) -> Result<(), Error<E, S>> {
+    self.apush(ServerQueueNode::notification(out), None).await
+  }
+
+  /// Set the number of messages a server of a
+  /// [fair](crate::fair_channel()) channel receives from this client each
+  /// time it's the client's turn.  The default weight is `1`.
+  ///
+  /// This allows a client that serves, for instance, several users to be
+  /// given a larger share of the server's attention than other clients.  The
+  /// weight has no effect on other types of channels.
+  ///
+  /// The new weight applies to messages sent after the call.  Clones of the
+  /// client start out with the weight of the original.
+  ///
+  /// # Panics
+  /// Panics if `weight` is zero.
+  pub fn set_weight(&mut self, weight: u32) {
+    assert!(weight > 0, "Client weight must be non-zero");
+    self.weight = weight;
+  }
+
+  /// Make the client keep the reply slot of its last request, and reuse it
+  /// for the next one, rather than allocating a new one for each request.
+  ///
+  /// This saves an allocation per request for clients that send requests
+  /// one after the other.  A slot is only reused once the server is done
+  /// with it;  if, for instance, a request timed out and the server still
+  /// holds on to its reply context, a new slot is allocated as usual.
+  /// Handles returned by [`Client::post()`] and [`Client::send_stream()`]
+  /// own their slots, so these are never kept for reuse.
+  ///
+  /// Clones of the client start out reusing slots if the original does, but
+  /// each clone has a slot of its own.
+  pub fn set_reuse_slot(&mut self, reuse: bool) {
+    self.cache = if reuse { Some(Mutex::new(None)) } else { None };
+  }
+
+  /// Returns a reply context in the `Queued` state for a new request.  If
+  /// the client reuses reply slots, and its cached one is no longer in use,
+  /// return it rather than creating a new one.
+  fn reply_context(&self) -> InnerReplyContext<R, E, P> {
+    // A client that is being used from several threads at once will find
+    // the cache locked, or empty, and simply allocates a new reply context.
+    let cached = self
+      .cache
+      .as_ref()
+      .and_then(|cache| cache.try_lock().ok()?.take());
+    match cached {
+      Some(mut rctx) => {
+        if rctx.reset() {
+          rctx
+        } else {
+          // Still in use, most likely by a server which hasn't dropped the
+          // reply context of a request that timed out.
+          InnerReplyContext::new()
+        }
+      }
+      None => InnerReplyContext::new()
+    }
+  }
+
+  /// Keep a reply context for reuse by the next request, if the client
+  /// reuses reply slots.
+  fn recycle(&self, rctx: InnerReplyContext<R, E, P>) {
+    if let Some(cache) = &self.cache {
+      if let Ok(mut cached) = cache.try_lock() {
+        *cached = Some(rctx);
+      }
+    }
+  }
+
+  /// Put a message with priority `prio` on the server's queue and return the
+  /// reply context the caller should wait on.
+  ///
+  /// If the queue is full, wait for space to become available, giving up at
+  /// `deadline` if one is given.
+  fn enqueue(
+    &self,
+    out: S,
+    prio: i32,
+    deadline: Option<Instant>
+  ) -> Result<InnerReplyContext<R, E, P>, Error<E, S>> {
+    // Reuse the client's reply context if it's able to, otherwise create a
+    // per-call reply context.
+    let rctx = self.reply_context();
+
+    let node = ServerQueueNode::new(out, rctx.clone()).with_priority(prio);
+    self.push(node, deadline)?;
+
+    Ok(rctx)
+  }
+
+  /// Same as [`Client::enqueue()`], but fail rather than wait if the queue is
+  /// full.
+  fn try_enqueue(
+    &self,
+    out: S
+  ) -> Result<InnerReplyContext<R, E, P>, Error<E, S>> {
+    let rctx = self.reply_context();
+
+    let node = ServerQueueNode::new(out, rctx.clone())
+      .with_sender(self.id, self.weight);
     let srvq = match self.srvq.upgrade() {
       Some(srvq) => srvq,
-      None => return Err(Error::ServerDisappeared)
+      None => return Err(Error::ServerDisappeared(node.into_msg()))
     };
+    srvq.try_push(node).map_err(push_error)?;
 
-    // Create a per-call reply context.
-    // This context could be created when the Client object is being created
-    // and stored in the context, and thus be reused for reach client call.
-    // One side-effect is that some of the state semantics becomes more
-    // complicated.
-    // The central repo has such an implementation checked in, but it seems to
-    // have some more corner cases that aren't properly handled.
-    let rctx = InnerReplyContext::new();
+    Ok(rctx)
+  }
 
-    srvq.push(ServerQueueNode {
-      msg: out,
-      reply: rctx.clone()
-    });
+  /// Same as [`Client::enqueue()`], but for use in `async` contexts.
+  async fn aenqueue(
+    &self,
+    out: S,
+    prio: i32,
+    deadline: Option<Instant>
+  ) -> Result<InnerReplyContext<R, E, P>, Error<E, S>> {
+    let rctx = self.reply_context();
 
-    // Drop the strong server queue ref immediately so it's not held as a
-    // strong ref while we're waiting for a reply.
-    drop(srvq);
+    let node = ServerQueueNode::new(out, rctx.clone()).with_priority(prio);
+    self.apush(node, deadline).await?;
 
-    let reply = rctx.get()?;
-    Ok(reply)
+    Ok(rctx)
   }
 
-  /// Same as [`Client::send()`] but for use in `async` contexts.
-  pub async fn asend(&self, out: S) -> Result<R, Error<E>> {
+  /// Put a node on the server's queue.  If the queue is full, wait for space
+  /// to become available, giving up at `deadline` if one is given.
+  fn push(
+    &self,
+    node: ServerQueueNode<S, R, E, P>,
+    deadline: Option<Instant>
+  ) -> Result<(), Error<E, S>> {
+    // Make sure the server still lives; Weak -> Arc
     let srvq = match self.srvq.upgrade() {
       Some(srvq) => srvq,
-      None => return Err(Error::ServerDisappeared)
+      None => return Err(Error::ServerDisappeared(node.into_msg()))
     };
 
-    let rctx = InnerReplyContext::new();
+    // The strong server queue ref is dropped on return, so it's not held
+    // while the caller is waiting for a reply.
+    let node = node.with_sender(self.id, self.weight);
+    srvq.push(node, deadline).map_err(push_error)
+  }
 
-    srvq.push(ServerQueueNode {
-      msg: out,
-      reply: rctx.clone()
-    });
+  /// Same as [`Client::push()`], but for use in `async` contexts.
+  async fn apush(
+    &self,
+    node: ServerQueueNode<S, R, E, P>,
+    deadline: Option<Instant>
+  ) -> Result<(), Error<E, S>> {
+    let srvq = match self.srvq.upgrade() {
+      Some(srvq) => srvq,
+      None => return Err(Error::ServerDisappeared(node.into_msg()))
+    };
+
+    let node = node.with_sender(self.id, self.weight);
+    srvq.apush(node, deadline).await.map_err(push_error)
+  }
 
-    // Drop the strong server queue ref immediately so it's not held as a
-    // strong ref while we're waiting for a reply.
-    drop(srvq);
+  /// Create the queue nodes for a batch of messages, along with the reply
+  /// contexts to wait on.
+  #[allow(clippy::type_complexity)]
+  fn batch<I>(
+    &self,
+    msgs: I
+  ) -> (
+    VecDeque<ServerQueueNode<S, R, E, P>>,
+    Vec<InnerReplyContext<R, E, P>>
+  )
+  where
+    I: IntoIterator<Item = S>
+  {
+    msgs
+      .into_iter()
+      .map(|msg| {
+        let rctx = InnerReplyContext::new();
+        let node = ServerQueueNode::new(msg, rctx.clone())
+          .with_sender(self.id, self.weight);
+        (node, rctx)
+      })
+      .unzip()
+  }
 
-    let result = rctx.aget().await?;
+  /// Put a batch of nodes on the server's queue, pushing as many at a time
+  /// as there is space for, and waiting for space to become available in
+  /// between.
+  ///
+  /// Returns the errors for the nodes that could not be pushed, which are
+  /// always the last ones of the batch.
+  fn push_many(
+    &self,
+    mut nodes: VecDeque<ServerQueueNode<S, R, E, P>>
+  ) -> Vec<Error<E, S>> {
+    let srvq = match self.srvq.upgrade() {
+      Some(srvq) => srvq,
+      None => return push_errors(PushError::Disconnected(nodes))
+    };
+    loop {
+      nodes = match srvq.push_some(nodes) {
+        Ok(nodes) => nodes,
+        Err(e) => return push_errors(e)
+      };
+      // The queue is full; wait for space for the next node.
+      let node = match nodes.pop_front() {
+        Some(node) => node,
+        None => return Vec::new()
+      };
+      if let Err(e) = srvq.push(node, None) {
+        return push_errors(e.map(|node| {
+          nodes.push_front(node);
+          nodes
+        }));
+      }
+    }
+  }
 
-    Ok(result)
+  /// Same as [`Client::push_many()`], but for use in `async` contexts.
+  async fn apush_many(
+    &self,
+    mut nodes: VecDeque<ServerQueueNode<S, R, E, P>>
+  ) -> Vec<Error<E, S>> {
+    let srvq = match self.srvq.upgrade() {
+      Some(srvq) => srvq,
+      None => return push_errors(PushError::Disconnected(nodes))
+    };
+    loop {
+      nodes = match srvq.push_some(nodes) {
+        Ok(nodes) => nodes,
+        Err(e) => return push_errors(e)
+      };
+      let node = match nodes.pop_front() {
+        Some(node) => node,
+        None => return Vec::new()
+      };
+      if let Err(e) = srvq.apush(node, None).await {
+        return push_errors(e.map(|node| {
+          nodes.push_front(node);
+          nodes
+        }));
+      }
+    }
   }
 }
 
+/// Translate a failure to push a batch of nodes onto the server queue into
+/// one client error per node.
+fn push_errors<S, R, E, P>(
+  err: PushError<VecDeque<ServerQueueNode<S, R, E, P>>>
+) -> Vec<Error<E, S>> {
+  match err {
+    PushError::Full(nodes) => nodes
+      .into_iter()
+      .map(|node| push_error(PushError::Full(node)))
+      .collect(),
+    PushError::Closed(nodes) => nodes
+      .into_iter()
+      .map(|node| push_error(PushError::Closed(node)))
+      .collect(),
+    PushError::Disconnected(nodes) => nodes
+      .into_iter()
+      .map(|node| push_error(PushError::Disconnected(node)))
+      .collect()
+  }
+}
 
-impl<S, R, E> Clone for Client<S, R, E> {
+/// Translate a failure to push a node onto the server queue into a client
+/// error, handing back the message.
+fn push_error<S, R, E, P>(
+  err: PushError<ServerQueueNode<S, R, E, P>>
+) -> Error<E, S> {
+  match err {
+    PushError::Full(node) => Error::QueueFull(node.into_msg()),
+    PushError::Closed(node) => Error::ServerClosed(node.into_msg()),
+    PushError::Disconnected(node) => Error::ServerDisappeared(node.into_msg())
+  }
+}
+
+
+impl<S, R, E, P> Clone for Client<S, R, E, P> {
   /// Clone a client.
   ///
   /// When a client is cloned the new object will be linked to the same server,
@@ -115,10 +879,397 @@ impl<S, R, E> Clone for Client<S, R, E> {
   /// make new independent calls to the server without any risk of collision
   /// between clone and the original client object.
   fn clone(&self) -> Self {
+    // A client whose server is gone can't send anything, so its identifier
+    // doesn't matter.
+    let id = match self.srvq.upgrade() {
+      Some(srvq) => srvq.add_client(),
+      None => 0
+    };
     Client {
-      srvq: Weak::clone(&self.srvq)
+      srvq: Weak::clone(&self.srvq),
+      id,
+      weight: self.weight,
+      cache: self.cache.as_ref().map(|_| Mutex::new(None))
+    }
+  }
+}
+
+impl<S, R, E, P> Drop for Client<S, R, E, P> {
+  /// Let the server know that one less client can send it messages.
+  fn drop(&mut self) {
+    if let Some(srvq) = self.srvq.upgrade() {
+      srvq.remove_client();
     }
   }
 }
 
+
+/// Handle used to collect the reply to a message sent using
+/// [`Client::post()`].
+///
+/// The handle can also be `.await`ed in `async` contexts.
+pub struct WaitReply<S, R, E, P = ()> {
+  /// Only `None` once the reply context has been passed on to a future.
+  rctx: Option<InnerReplyContext<R, E, P>>,
+  _marker: PhantomData<fn() -> S>
+}
+
+impl<S, R, E, P> WaitReply<S, R, E, P>
+where
+  S: 'static + Send,
+  R: 'static + Send,
+  E: 'static + Send,
+  P: 'static + Send
+{
+  /// Block and wait for the reply.
+  ///
+  /// Return values are the same as for [`Client::send()`].
+  pub fn wait(mut self) -> Result<R, Error<E, S>> {
+    // Unwrap is okay; the reply context is only taken by consuming methods.
+    let rctx = self.rctx.take().unwrap();
+
+    let reply = rctx.get()?;
+    Ok(reply)
+  }
+
+  /// Same as [`WaitReply::wait()`], but give up waiting for the reply after
+  /// `timeout` has elapsed.
+  ///
+  /// If the server has not replied within `timeout`, `Err(Error::Timeout)`
+  /// will be returned and the request is cancelled.
+  pub fn wait_timeout(self, timeout: Duration) -> Result<R, Error<E, S>> {
+    match Instant::now().checked_add(timeout) {
+      Some(deadline) => self.wait_deadline(deadline),
+      None => self.wait()
+    }
+  }
+
+  /// Same as [`WaitReply::wait_timeout()`], but give up waiting for the
+  /// reply at a specific point in time rather than after a duration.
+  pub fn wait_deadline(mut self, deadline: Instant) -> Result<R, Error<E, S>> {
+    let rctx = self.rctx.take().unwrap();
+
+    let reply = rctx.get_deadline(deadline)?;
+    Ok(reply)
+  }
+
+  /// Return the reply if it has arrived, without blocking.
+  ///
+  /// The handle is consumed once the reply has been returned.  If the server
+  /// hasn't replied yet the handle is handed back in `Err`, so it can be used
+  /// to check again later, or to wait for the reply.
+  ///
+  /// # Example
+  /// ```
+  /// use ump::channel;
+  ///
+  /// let (server, client) = channel::<u32, u32, ()>();
+  /// let wrply = client.post(21).unwrap();
+  ///
+  /// // Still waiting for the server.
+  /// let wrply = wrply.try_get().unwrap_err();
+  ///
+  /// let (n, rctx) = server.wait().unwrap();
+  /// rctx.reply(n * 2).unwrap();
+  /// match wrply.try_get() {
+  ///   Ok(reply) => assert_eq!(reply.unwrap(), 42),
+  ///   Err(_) => panic!("Expected a reply")
+  /// }
+  /// ```
+  pub fn try_get(mut self) -> Result<Result<R, Error<E, S>>, Self> {
+    // Unwrap is okay; the reply context is only taken by consuming methods.
+    let rctx = self.rctx.as_ref().unwrap();
+    match rctx.try_get() {
+      Some(res) => {
+        self.rctx = None;
+        Ok(res.map_err(Error::from))
+      }
+      None => Err(self)
+    }
+  }
+
+  /// Block and wait for the next progress update sent by the server using
+  /// [`ReplyContext::progress()`](crate::ReplyContext::progress()).
+  ///
+  /// Returns `None` once the reply has arrived and all the updates sent
+  /// before it have been returned; at that point [`WaitReply::wait()`] will
+  /// return immediately.
+  ///
+  /// # Example
+  /// ```
+  /// use std::thread;
+  /// use ump::progress_channel;
+  ///
+  /// let (server, client) = progress_channel::<(), (), (), &str>();
+  /// let server_thread = thread::spawn(move || {
+  ///   let (_, rctx) = server.wait().unwrap();
+  ///   rctx.progress("Downloading").unwrap();
+  ///   rctx.progress("Installing").unwrap();
+  ///   rctx.reply(()).unwrap();
+  /// });
+  /// let mut wrply = client.post(()).unwrap();
+  /// assert_eq!(wrply.wait_progress(), Some("Downloading"));
+  /// assert_eq!(wrply.wait_progress(), Some("Installing"));
+  /// assert_eq!(wrply.wait_progress(), None);
+  /// wrply.wait().unwrap();
+  /// server_thread.join().unwrap();
+  /// ```
+  pub fn wait_progress(&mut self) -> Option<P> {
+    self.rctx.as_ref()?.next_progress()
+  }
+
+  /// Return the next progress update if one has arrived, without blocking.
+  ///
+  /// This allows, for instance, a UI thread to poll for progress in between
+  /// doing other work.
+  pub fn try_progress(&mut self) -> Option<P> {
+    self.rctx.as_ref()?.try_progress()
+  }
+
+  /// Same as [`WaitReply::wait_progress()`], but for use in `async`
+  /// contexts.
+  pub async fn async_progress(&mut self) -> Option<P> {
+    let rctx = self.rctx.as_ref()?;
+    std::future::poll_fn(|ctx| rctx.poll_next_progress(ctx)).await
+  }
+}
+
+impl<S, R, E, P> Drop for WaitReply<S, R, E, P> {
+  /// If the handle is dropped before the reply has been collected, then
+  /// cancel the request.
+  fn drop(&mut self) {
+    if let Some(rctx) = self.rctx.take() {
+      rctx.cancel();
+    }
+  }
+}
+
+impl<S, R, E, P> IntoFuture for WaitReply<S, R, E, P>
+where
+  S: 'static + Send,
+  R: 'static + Send,
+  E: 'static + Send,
+  P: 'static + Send
+{
+  type Output = Result<R, Error<E, S>>;
+  type IntoFuture = ReplyFuture<S, R, E, P>;
+
+  fn into_future(mut self) -> Self::IntoFuture {
+    let rctx = self.rctx.take().unwrap();
+    ReplyFuture {
+      fut: rctx.aget(),
+      _marker: PhantomData
+    }
+  }
+}
+
+/// Future returned when a [`WaitReply`] is `.await`ed.
+pub struct ReplyFuture<S, R, E, P = ()> {
+  fut: WaitReplyFuture<R, E, P>,
+  _marker: PhantomData<fn() -> S>
+}
+
+impl<S, R, E, P> Future for ReplyFuture<S, R, E, P>
+where
+  S: 'static + Send,
+  R: 'static + Send,
+  E: 'static + Send,
+  P: 'static + Send
+{
+  type Output = Result<R, Error<E, S>>;
+  fn poll(self: Pin<&mut Self>, ctx: &mut Context<'_>) -> Poll<Self::Output> {
+    let this = self.get_mut();
+    Pin::new(&mut this.fut).poll(ctx).map_err(Error::from)
+  }
+}
+
+
+/// Stream of reply items to a message sent using [`Client::send_stream()`].
+///
+/// Use [`ReplyStream::wait_next()`] to block while waiting for the next item,
+/// or turn the stream into an [`Iterator`] using
+/// [`into_iter()`](ReplyStream::into_iter()).  In `async` contexts use
+/// [`ReplyStream::async_next()`] instead, or, if the `futures` feature is
+/// enabled, use it as a `futures::Stream`.
+///
+/// Dropping the stream before it has ended cancels the request.
+pub struct ReplyStream<S, R, E, P = ()> {
+  rctx: InnerReplyContext<R, E, P>,
+  _marker: PhantomData<fn() -> S>
+}
+
+impl<S, R, E, P> ReplyStream<S, R, E, P>
+where
+  S: 'static + Send,
+  R: 'static + Send,
+  E: 'static + Send,
+  P: 'static + Send
+{
+  /// Block and wait for the next item.
+  ///
+  /// Returns `None` once the stream has ended.  If an error occurs it is
+  /// returned as the last item of the stream.
+  pub fn wait_next(&mut self) -> Option<Result<R, Error<E, S>>> {
+    self.rctx.next_item().map(|res| res.map_err(Error::from))
+  }
+
+  /// Same as [`ReplyStream::wait_next()`], but for use in `async` contexts.
+  pub async fn async_next(&mut self) -> Option<Result<R, Error<E, S>>> {
+    let rctx = &self.rctx;
+    std::future::poll_fn(|ctx| rctx.poll_next_item(ctx))
+      .await
+      .map(|res| res.map_err(Error::from))
+  }
+}
+
+impl<S, R, E, P> IntoIterator for ReplyStream<S, R, E, P>
+where
+  S: 'static + Send,
+  R: 'static + Send,
+  E: 'static + Send,
+  P: 'static + Send
+{
+  type Item = Result<R, Error<E, S>>;
+  type IntoIter = ReplyIter<S, R, E, P>;
+
+  fn into_iter(self) -> Self::IntoIter {
+    ReplyIter { stream: self }
+  }
+}
+
+/// Blocking [`Iterator`] over the items of a [`ReplyStream`].
+pub struct ReplyIter<S, R, E, P = ()> {
+  stream: ReplyStream<S, R, E, P>
+}
+
+impl<S, R, E, P> Iterator for ReplyIter<S, R, E, P>
+where
+  S: 'static + Send,
+  R: 'static + Send,
+  E: 'static + Send,
+  P: 'static + Send
+{
+  type Item = Result<R, Error<E, S>>;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    self.stream.wait_next()
+  }
+}
+
+#[cfg(feature = "futures")]
+impl<S, R, E, P> futures_core::Stream for ReplyStream<S, R, E, P>
+where
+  S: 'static + Send,
+  R: 'static + Send,
+  E: 'static + Send,
+  P: 'static + Send
+{
+  type Item = Result<R, Error<E, S>>;
+
+  fn poll_next(
+    self: Pin<&mut Self>,
+    ctx: &mut Context<'_>
+  ) -> Poll<Option<Self::Item>> {
+    self
+      .rctx
+      .poll_next_item(ctx)
+      .map(|item| item.map(|res| res.map_err(Error::from)))
+  }
+}
+
+impl<S, R, E, P> Drop for ReplyStream<S, R, E, P> {
+  /// If the stream is dropped before it has ended, then cancel the request.
+  fn drop(&mut self) {
+    self.rctx.cancel();
+  }
+}
+
+
+#[cfg(test)]
+mod tests {
+  use std::sync::Arc;
+  use std::thread;
+
+  use super::Client;
+  use crate::{channel, Server};
+
+  /// Address of the reply slot the client keeps for its next request.
+  fn cached_slot<S, R, E, P>(client: &Client<S, R, E, P>) -> Option<usize> {
+    let cached = client.cache.as_ref().unwrap().lock().unwrap();
+    cached.as_ref().map(|rctx| Arc::as_ptr(&rctx.slot) as usize)
+  }
+
+  fn echo_server(server: Server<u32, u32, ()>) -> thread::JoinHandle<()> {
+    thread::spawn(move || {
+      while let Some((n, rctx)) = server.wait() {
+        rctx.reply(n).unwrap();
+      }
+    })
+  }
+
+
+  #[test]
+  fn sequential_sends_reuse_slot() {
+    let (server, mut client) = channel::<u32, u32, ()>();
+    client.set_reuse_slot(true);
+    let server_thread = echo_server(server);
+
+    assert_eq!(client.send(0).unwrap(), 0);
+    let slot = cached_slot(&client);
+    assert!(slot.is_some());
+    for n in 1..1000 {
+      assert_eq!(client.send(n).unwrap(), n);
+      assert_eq!(cached_slot(&client), slot);
+    }
+
+    drop(client);
+    server_thread.join().unwrap();
+  }
+
+
+  #[test]
+  fn sequential_asends_reuse_slot() {
+    let tokrt = tokio::runtime::Builder::new_current_thread()
+      .build()
+      .unwrap();
+
+    let (server, mut client) = channel::<u32, u32, ()>();
+    client.set_reuse_slot(true);
+    let server_thread = echo_server(server);
+
+    tokrt.block_on(async {
+      assert_eq!(client.asend(0).await.unwrap(), 0);
+      let slot = cached_slot(&client);
+      assert!(slot.is_some());
+      for n in 1..1000 {
+        assert_eq!(client.asend(n).await.unwrap(), n);
+        assert_eq!(cached_slot(&client), slot);
+      }
+    });
+
+    drop(client);
+    server_thread.join().unwrap();
+  }
+
+
+  #[test]
+  fn slot_in_use_not_reused() {
+    let (_server, mut client) = channel::<u32, u32, ()>();
+    client.set_reuse_slot(true);
+
+    // Hold on to the slot, as a server still holding on to the reply context
+    // of a request that timed out would.
+    let first = client.reply_context();
+    let held = first.clone();
+    client.recycle(first);
+    let second = client.reply_context();
+    assert!(!Arc::ptr_eq(&second.slot, &held.slot));
+
+    // Nobody else holds on to the new slot, so it's reused.
+    let slot = Arc::as_ptr(&second.slot);
+    client.recycle(second);
+    assert_eq!(Arc::as_ptr(&client.reply_context().slot), slot);
+  }
+}
+
 // vim: set ft=rust et sw=2 ts=2 sts=2 cinoptions=2 tw=79 :
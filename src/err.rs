@@ -1,25 +1,52 @@
 use std::fmt;
 
 /// Module-specific error codes.
-#[derive(Debug)]
-pub enum Error<E> {
+///
+/// The `S` type parameter is the message type sent by clients.  Errors that
+/// occur before the server received a message hand the message back to the
+/// caller, so it can be resent to another server without having to be
+/// cloned up front.
+pub enum Error<E, S> {
   /// The server object has shut down.  This happens when clients:
   /// - attempt to send messages to a server that has been deallocated.
   /// - have their requests dropped from the serrver's queue because the
   ///   server itself was deallocated.
-  ServerDisappeared,
+  ///
+  /// The message that was never received by the server is returned.
+  ServerDisappeared(S),
+
+  /// The server has been [closed](crate::Server::close()) and no longer
+  /// accepts new messages.
+  ///
+  /// The message that was never received by the server is returned.
+  ServerClosed(S),
+
+  /// The server's queue is full.  This is only returned by calls that don't
+  /// wait for space to become available in a
+  /// [bounded](crate::channel_bounded()) queue.
+  ///
+  /// The message that could not be queued is returned.
+  QueueFull(S),
 
   /// The message was delivered to the server, but the reply context was
   /// released before sending back a reply.
   NoReply,
 
+  /// The server did not reply before the deadline expired.
+  Timeout,
+
+  /// The server panicked while processing the message.  Holds the panic
+  /// message if the handler was run using
+  /// [`ReplyContext::run()`](crate::ReplyContext::run()).
+  ServerPanicked(String),
+
   /// Application-specific error.
   /// The `E` type is typically declared as the third generic parameter to
   /// [`channel`](crate::channel()).
   App(E)
 }
 
-impl<E> Error<E> {
+impl<E, S> Error<E, S> {
   pub fn into_apperr(self) -> Option<E> {
     match self {
       Error::App(e) => Some(e),
@@ -32,28 +59,87 @@ impl<E> Error<E> {
       _ => panic!("Not an Error::App")
     }
   }
+
+  /// Return the message that was never received by the server, if any.
+  pub fn into_msg(self) -> Option<S> {
+    match self {
+      Error::ServerDisappeared(msg)
+      | Error::ServerClosed(msg)
+      | Error::QueueFull(msg) => Some(msg),
+      _ => None
+    }
+  }
 }
 
-impl<E: fmt::Debug> std::error::Error for Error<E> {}
+impl<E: fmt::Debug, S> std::error::Error for Error<E, S> {}
 
-impl<E> From<crate::rctx::Error<E>> for Error<E> {
+impl<E, S: 'static> From<crate::rctx::Error<E>> for Error<E, S> {
   fn from(err: crate::rctx::Error<E>) -> Self {
     match err {
-      crate::rctx::Error::Aborted => Error::ServerDisappeared,
+      crate::rctx::Error::Aborted(msg) => match msg.downcast::<S>() {
+        Ok(msg) => Error::ServerDisappeared(*msg),
+        Err(_) => {
+          // We're *really* in trouble if this happens ..
+          panic!("Unexpected aborted message type");
+        }
+      },
       crate::rctx::Error::NoReply => Error::NoReply,
+      crate::rctx::Error::Timeout => Error::Timeout,
+      crate::rctx::Error::Panicked(msg) => Error::ServerPanicked(msg),
       crate::rctx::Error::App(e) => Error::App(e)
     }
   }
 }
 
-impl<E: fmt::Debug> fmt::Display for Error<E> {
+// Written by hand rather than derived so that the message type isn't required
+// to implement `Debug`.
+impl<E: fmt::Debug, S> fmt::Debug for Error<E, S> {
   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-    match &*self {
-      Error::ServerDisappeared => write!(f, "Server disappeared"),
+    match self {
+      Error::ServerDisappeared(_) => write!(f, "ServerDisappeared(..)"),
+      Error::ServerClosed(_) => write!(f, "ServerClosed(..)"),
+      Error::QueueFull(_) => write!(f, "QueueFull(..)"),
+      Error::NoReply => write!(f, "NoReply"),
+      Error::Timeout => write!(f, "Timeout"),
+      Error::ServerPanicked(msg) => {
+        f.debug_tuple("ServerPanicked").field(msg).finish()
+      }
+      Error::App(err) => f.debug_tuple("App").field(err).finish()
+    }
+  }
+}
+
+impl<E: fmt::Debug, S> fmt::Display for Error<E, S> {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      Error::ServerDisappeared(_) => write!(f, "Server disappeared"),
+      Error::ServerClosed(_) => write!(f, "Server closed"),
+      Error::QueueFull(_) => write!(f, "Server queue is full"),
       Error::NoReply => write!(f, "Server didn't reply"),
+      Error::Timeout => write!(f, "Timed out waiting for reply"),
+      Error::ServerPanicked(msg) => write!(f, "Server panicked; {}", msg),
       Error::App(err) => write!(f, "Application error; {:?}", err)
     }
   }
 }
 
+
+/// Returned by [`Server`](crate::Server) calls that don't wait indefinitely,
+/// to tell that no more messages will arrive.
+///
+/// This happens once the server has been [closed](crate::Server::close()),
+/// or every [`Client`](crate::Client) has been dropped, and all the messages
+/// that were queued have been received.  It lets event loops tell this apart
+/// from `Ok(None)`, which means that no message was available yet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Closed;
+
+impl std::error::Error for Closed {}
+
+impl fmt::Display for Closed {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "No more messages will arrive")
+  }
+}
+
 // vim: set ft=rust et sw=2 ts=2 sts=2 cinoptions=2 tw=79 :
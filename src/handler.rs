@@ -0,0 +1,115 @@
+//! Message handlers run by [`Server::serve()`](crate::Server::serve()) and
+//! [`Server::async_serve()`](crate::Server::async_serve()).
+//!
+//! A handler processes one message at a time and returns the reply, or an
+//! error, which the server passes back to the client.  This saves servers
+//! that don't need anything more than this from having to write their own
+//! receive loop.
+
+use std::future::Future;
+use std::sync::Weak;
+
+use crate::server::ServerQueueNode;
+use crate::srvq::NotifyQueue;
+
+/// Processes messages received by a server, one at a time.
+///
+/// Closures taking a message and returning a `Result` are handlers, so simple
+/// servers don't need to implement the trait themselves.
+///
+/// # Example
+/// ```
+/// use std::thread;
+/// use ump::{channel, Handler};
+///
+/// /// Keeps a running total of the numbers it has been sent.
+/// struct Adder {
+///   total: u64
+/// }
+///
+/// impl Handler<u64, u64, ()> for Adder {
+///   fn handle(&mut self, n: u64) -> Result<u64, ()> {
+///     self.total += n;
+///     Ok(self.total)
+///   }
+/// }
+///
+/// let (server, client) = channel::<u64, u64, ()>();
+/// let server_thread =
+///   thread::spawn(move || server.serve(Adder { total: 0 }));
+///
+/// assert_eq!(client.send(1).unwrap(), 1);
+/// assert_eq!(client.send(2).unwrap(), 3);
+/// drop(client);
+///
+/// server_thread.join().unwrap();
+/// ```
+pub trait Handler<S, R, E> {
+  /// Process a message.  `Ok` is sent back to the client as the reply, and
+  /// `Err` is sent back as an [application error](crate::Error::App).
+  fn handle(&mut self, msg: S) -> Result<R, E>;
+}
+
+impl<S, R, E, F> Handler<S, R, E> for F
+where
+  F: FnMut(S) -> Result<R, E>
+{
+  fn handle(&mut self, msg: S) -> Result<R, E> {
+    self(msg)
+  }
+}
+
+/// Same as [`Handler`], but processes messages in an `async` context.
+///
+/// Closures taking a message and returning a `Future` that resolves to a
+/// `Result` are handlers.
+pub trait AsyncHandler<S, R, E> {
+  /// Process a message.  The outcome is sent back to the client in the same
+  /// way as for [`Handler::handle()`].
+  fn handle(&mut self, msg: S) -> impl Future<Output = Result<R, E>> + Send;
+}
+
+impl<S, R, E, F, Fut> AsyncHandler<S, R, E> for F
+where
+  F: FnMut(S) -> Fut,
+  Fut: Future<Output = Result<R, E>> + Send
+{
+  fn handle(&mut self, msg: S) -> impl Future<Output = Result<R, E>> + Send {
+    self(msg)
+  }
+}
+
+
+/// Tells a server running a [`Handler`] to stop.
+///
+/// Created using
+/// [`Server::shutdown_token()`](crate::Server::shutdown_token()).
+/// The token can be cloned and passed to other threads or tasks, and it
+/// doesn't keep the server alive.
+pub struct ShutdownToken<S, R, E, P = ()> {
+  pub(crate) srvq: Weak<NotifyQueue<ServerQueueNode<S, R, E, P>>>
+}
+
+impl<S, R, E, P> ShutdownToken<S, R, E, P> {
+  /// Stop the server.
+  ///
+  /// This [closes](crate::Server::close()) the server, so no new messages are
+  /// accepted.  Messages that are already in the queue are still handled,
+  /// after which the server's run loop returns.  Does nothing if the server
+  /// is already gone.
+  pub fn shutdown(&self) {
+    if let Some(srvq) = self.srvq.upgrade() {
+      srvq.close();
+    }
+  }
+}
+
+impl<S, R, E, P> Clone for ShutdownToken<S, R, E, P> {
+  fn clone(&self) -> Self {
+    ShutdownToken {
+      srvq: Weak::clone(&self.srvq)
+    }
+  }
+}
+
+// vim: set ft=rust et sw=2 ts=2 sts=2 cinoptions=2 tw=79 :
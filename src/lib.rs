@@ -16,6 +16,19 @@
 //!
 //! A client, in a separate thread or task, calls
 //! [`Client::send()`]/[`Client::asend()`] to send a message to the server.
+//! A client that doesn't want to wait for the reply right away can instead
+//! call [`Client::post()`] and collect the reply later using the returned
+//! [`WaitReply`] handle.  Clients that only want to inform the server of
+//! something, without waiting for any reply, can use [`Client::notify()`],
+//! and a batch of messages can be sent in one go using
+//! [`Client::send_many()`].  Large replies can be streamed back in chunks by
+//! sending the message using [`Client::send_stream()`].  Servers that take a
+//! long time to process messages can keep their clients informed using
+//! [`ReplyContext::progress()`] on channels created using
+//! [`progress_channel`].  Channels created using [`priority_channel`] let
+//! urgent messages, sent using [`Client::send_with_priority()`], skip ahead
+//! of other messages waiting in the server's queue, and channels created
+//! using [`fair_channel`] keep a busy client from starving the others.
 //!
 //! The server's wait call returns two objects:  The message sent by the
 //! client, and a [`ReplyContext`].
@@ -25,10 +38,29 @@
 //! return a reply message to the client.
 //!
 //! Typically the server calls wait again to wait for next message from a
-//! client.
+//! client.  Servers that amortise work over several messages can instead
+//! receive all the messages waiting in the queue at once using
+//! [`Server::wait_batch()`].
 //!
 //! The client receives the reply from the server and processes it.
 //!
+//! The server's wait calls return `None` once all clients have been dropped
+//! and all their messages have been received, so a server can simply loop
+//! until there's nobody left to serve.  To shut down gracefully while there
+//! are still clients, the server can call [`Server::close()`] to stop
+//! accepting new messages.  Its wait calls will keep returning the messages
+//! that were already queued, and then return `None`.
+//!
+//! Servers that reply to each message as it arrives don't need to write this
+//! loop themselves:  [`Server::serve()`] passes the messages to a [`Handler`]
+//! and replies using its results, until there's nobody left to serve or the
+//! server is stopped using a [`ShutdownToken`].
+//!
+//! # Features
+//! - `futures`: Adds `Server::into_stream()`, which turns a server into a
+//!   `futures::Stream` of incoming messages, so that stream combinators can be
+//!   used to process them.
+//!
 //! # Example
 //! ```
 //! use std::thread;
@@ -41,7 +73,7 @@
 //!  let server_thread = thread::spawn(move || {
 //!    // Wait for data to arrive from a client
 //!    println!("Server waiting for message ..");
-//!    let (data, mut rctx) = server.wait();
+//!    let (data, mut rctx) = server.wait().unwrap();
 //!
 //!    println!("Server received: '{}'", data);
 //!
@@ -86,9 +118,18 @@
 //!   safely terminate while there are reply contexts in-flight).
 //! - A cloned client is paired with the same server as its origin, but in all
 //!   other respects the clone and its origin are independent of each other.
+//! - A cloned server receives messages from the same queue as its origin. Each
+//!   message is received by exactly one server.
 //! - A client can be moved to a new thread.
 //! - Any permutation of sync/async server/clients can be combined.  `async`
 //!   code must use the async method variants when available.
+//! - The `async` client calls are cancellation safe:  If the future returned
+//!   by [`Client::asend()`] is dropped before it completes, the request is
+//!   withdrawn.  If it is still in the server's queue it will be skipped by
+//!   [`Server::wait()`]; if the server has already picked it up the server can
+//!   detect this using [`ReplyContext::is_cancelled()`].  A reply sent to a
+//!   client that has stopped waiting is handed back to the server in a
+//!   [`ReplyError`].
 //!
 //! ## Unstable invariants
 //!
@@ -102,20 +143,38 @@
 //!   future this may not be allowed. It is recommended that a new clone of the
 //!   client be created instead.
 
+mod backlog;
 mod client;
 mod err;
+mod handler;
 mod rctx;
 mod server;
+mod srvq;
+#[cfg(feature = "futures")]
+mod stream;
+mod timer;
+mod waker;
 
-pub use err::Error;
+pub use err::{Closed, Error};
 
 use std::sync::Arc;
 
-use sigq::Queue as NotifyQueue;
+use crate::backlog::Backlog;
+use crate::server::ServerQueueNode;
+use crate::srvq::NotifyQueue;
 
-pub use crate::client::Client;
-pub use crate::rctx::ReplyContext;
-pub use crate::server::Server;
+pub use crate::backlog::Priority;
+pub use crate::client::{
+  Client, ReplyFuture, ReplyIter, ReplyStream, WaitReply
+};
+pub use crate::handler::{AsyncHandler, Handler, ShutdownToken};
+pub use crate::rctx::{
+  Cancelled, ReplyContext, ReplyError, StreamReplyContext
+};
+pub use crate::server::{Incoming, Server};
+
+#[cfg(feature = "futures")]
+pub use crate::stream::ServerStream;
 
 /// Create a pair of linked [`Server`] and [`Client`] objects.
 ///
@@ -131,19 +190,180 @@ pub use crate::server::Server;
 /// new client object that is connected to the same server object, but is
 /// completely independent of the original client.
 ///
+/// The server can be [cloned](Server::clone()) as well, to let a pool of
+/// worker threads or tasks receive messages from the same clients.  Clients
+/// will only see [`Error::ServerDisappeared`] once every server has been
+/// dropped.
+///
 /// The `S` type parameter is the "send" data type that clients will transfer
 /// to the server.  The `R` type parameter is the "receive" data type that
 /// clients will receive from the server.  The `E` type parameter can be used
 /// to return application specific errors from the server to the client.
 pub fn channel<S, R, E>() -> (Server<S, R, E>, Client<S, R, E>) {
-  let srvq = Arc::new(NotifyQueue::new());
+  new_channel(Backlog::fifo(), None)
+}
+
+/// Create a pair of linked [`Server`] and [`Client`] objects, where the
+/// server's queue can hold at most `cap` messages.
+///
+/// This works like [`channel`], except that once `cap` messages are waiting
+/// in the server's queue, [`Client::send()`] will block (and
+/// [`Client::asend()`] will wait) until the server has picked up a message.
+/// [`Client::try_send()`] can be used to fail immediately with
+/// [`Error::QueueFull`] instead.  This allows the queue depth to be used for
+/// flow control between clients and a server that can't keep up.
+///
+/// # Panics
+/// Panics if `cap` is zero.
+pub fn channel_bounded<S, R, E>(
+  cap: usize
+) -> (Server<S, R, E>, Client<S, R, E>) {
+  assert!(cap > 0, "Queue capacity must be non-zero");
+  new_channel(Backlog::fifo(), Some(cap))
+}
+
+/// Create a pair of linked [`Server`] and [`Client`] objects, where the
+/// server can report progress to clients while processing their messages.
+///
+/// This works like [`channel`], except that the `P` type parameter is the
+/// progress update type.  The server sends updates using
+/// [`ReplyContext::progress()`], and clients receive them either through a
+/// callback passed to [`Client::send_with_progress()`], or from the
+/// [`WaitReply`] handle returned by [`Client::post()`].
+#[allow(clippy::type_complexity)]
+pub fn progress_channel<S, R, E, P>(
+) -> (Server<S, R, E, P>, Client<S, R, E, P>) {
+  new_channel(Backlog::fifo(), None)
+}
+
+/// Same as [`progress_channel`], but the server's queue can hold at most
+/// `cap` messages.  See [`channel_bounded`].
+///
+/// # Panics
+/// Panics if `cap` is zero.
+#[allow(clippy::type_complexity)]
+pub fn progress_channel_bounded<S, R, E, P>(
+  cap: usize
+) -> (Server<S, R, E, P>, Client<S, R, E, P>) {
+  assert!(cap > 0, "Queue capacity must be non-zero");
+  new_channel(Backlog::fifo(), Some(cap))
+}
+
+/// Create a pair of linked [`Server`] and [`Client`] objects, where the
+/// server receives messages in priority order rather than in the order they
+/// were sent.
+///
+/// This works like [`channel`], except that messages sent using
+/// [`Client::send_with_priority()`] and its variants with a higher priority
+/// are received before messages with a lower priority, regardless of the order
+/// they were sent in.  This allows urgent messages, like requests to cancel
+/// an operation, to skip ahead of bulk work waiting in the queue.  See
+/// [`Priority`] for details.
+///
+/// # Example
+/// ```
+/// use ump::{priority_channel, Priority};
+///
+/// let (server, client) = priority_channel::<&str, (), ()>(Priority::new());
+/// let bulk = client.post("bulk").unwrap();
+/// let urgent = client.post_with_priority("urgent", 10).unwrap();
+///
+/// let (msg, rctx) = server.wait().unwrap();
+/// assert_eq!(msg, "urgent");
+/// rctx.reply(()).unwrap();
+/// urgent.wait().unwrap();
+///
+/// let (msg, rctx) = server.wait().unwrap();
+/// assert_eq!(msg, "bulk");
+/// rctx.reply(()).unwrap();
+/// bulk.wait().unwrap();
+/// ```
+pub fn priority_channel<S, R, E>(
+  policy: Priority
+) -> (Server<S, R, E>, Client<S, R, E>) {
+  new_channel(Backlog::priority(policy), None)
+}
+
+/// Same as [`priority_channel`], but the server's queue can hold at most
+/// `cap` messages.  See [`channel_bounded`].
+///
+/// # Panics
+/// Panics if `cap` is zero.
+pub fn priority_channel_bounded<S, R, E>(
+  policy: Priority,
+  cap: usize
+) -> (Server<S, R, E>, Client<S, R, E>) {
+  assert!(cap > 0, "Queue capacity must be non-zero");
+  new_channel(Backlog::priority(policy), Some(cap))
+}
+
+/// Create a pair of linked [`Server`] and [`Client`] objects, where the
+/// server takes turns receiving messages from each client.
+///
+/// This works like [`channel`], except that each [`Client`] (including each
+/// [clone](Client::clone())) gets a queue of its own.  The server receives
+/// messages from the clients that have messages waiting in round-robin order,
+/// so a client that sends a lot of messages can't starve other clients.
+/// Messages from the same client are received in the order they were sent.
+///
+/// By default one message is received from a client per turn.  Use
+/// [`Client::set_weight()`] to let a client have more messages received per
+/// turn.
+///
+/// # Example
+/// ```
+/// use ump::fair_channel;
+///
+/// let (server, client) = fair_channel::<&str, (), ()>();
+/// let other = client.clone();
+/// let bulk: Vec<_> = (0..3).map(|_| client.post("bulk").unwrap()).collect();
+/// let urgent = other.post("urgent").unwrap();
+///
+/// // The other client's message is received after the first of the three
+/// // that were sent before it.
+/// let mut order = Vec::new();
+/// while let Ok(Some((msg, rctx))) = server.try_wait() {
+///   order.push(msg);
+///   rctx.reply(()).unwrap();
+/// }
+/// assert_eq!(order, vec!["bulk", "urgent", "bulk", "bulk"]);
+/// ```
+pub fn fair_channel<S, R, E>() -> (Server<S, R, E>, Client<S, R, E>) {
+  new_channel(Backlog::fair(), None)
+}
+
+/// Same as [`fair_channel`], but the server's queue can hold at most `cap`
+/// messages.  See [`channel_bounded`].
+///
+/// The capacity is shared by all clients;  a client that has filled the queue
+/// will still block other clients from sending messages until the server
+/// has received some of them.
+///
+/// # Panics
+/// Panics if `cap` is zero.
+pub fn fair_channel_bounded<S, R, E>(
+  cap: usize
+) -> (Server<S, R, E>, Client<S, R, E>) {
+  assert!(cap > 0, "Queue capacity must be non-zero");
+  new_channel(Backlog::fair(), Some(cap))
+}
+
+#[allow(clippy::type_complexity)]
+fn new_channel<S, R, E, P>(
+  q: Backlog<ServerQueueNode<S, R, E, P>>,
+  cap: Option<usize>
+) -> (Server<S, R, E, P>, Client<S, R, E, P>) {
+  let srvq = Arc::new(NotifyQueue::new(q, cap));
   let server = Server {
     srvq: Arc::clone(&srvq)
   };
 
   // Note: The client stores a weak reference to the server object
   let client = Client {
-    srvq: Arc::downgrade(&srvq)
+    srvq: Arc::downgrade(&srvq),
+    id: 0,
+    weight: 1,
+    cache: None
   };
 
   (server, client)
@@ -1,16 +1,24 @@
+use std::any::Any;
 use std::fmt;
 
 /// Module-specific error codes.
 #[derive(Debug)]
 pub enum Error<E> {
-  /// The reply was aborted.
-  Aborted,
+  /// The reply was aborted.  The message that was never delivered is
+  /// returned, type-erased.
+  Aborted(Box<dyn Any + Send>),
 
   /// The public [`ReplyContext`] object is required to reply with a value.
   /// If it does not the endpoint waiting to receive a value will abort and
   /// return this error.
   NoReply,
 
+  /// No reply arrived before the deadline expired.
+  Timeout,
+
+  /// The replier panicked.  Holds the panic message.
+  Panicked(String),
+
   /// An application-specific error occurred.
   App(E)
 }
@@ -19,12 +27,73 @@ impl<E: fmt::Debug> std::error::Error for Error<E> {}
 
 impl<E: fmt::Debug> fmt::Display for Error<E> {
   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-    match &*self {
-      Error::Aborted => write!(f, "Aborted call"),
+    match self {
+      Error::Aborted(_) => write!(f, "Aborted call"),
       Error::NoReply => write!(f, "Application failed to reply"),
+      Error::Timeout => write!(f, "Timed out waiting for reply"),
+      Error::Panicked(msg) => write!(f, "Replier panicked; {}", msg),
       Error::App(err) => write!(f, "Application error; {:?}", err)
     }
   }
 }
 
+/// Error returned when a reply could not be delivered to the client.
+///
+/// The `T` type parameter is the type of the value that was being sent back;
+/// the value is handed back to the replier so it can, for instance, undo any
+/// side effects of processing the request.
+pub enum ReplyError<T> {
+  /// The client stopped waiting for a reply, for instance because it timed
+  /// out or because its `async` call or [`WaitReply`](crate::WaitReply) was
+  /// dropped.  The reply that was never received is returned.
+  Cancelled(T)
+}
+
+impl<T> ReplyError<T> {
+  /// Return the value that was never delivered to the client.
+  pub fn into_inner(self) -> T {
+    match self {
+      ReplyError::Cancelled(v) => v
+    }
+  }
+}
+
+impl<T> std::error::Error for ReplyError<T> {}
+
+// Written by hand rather than derived so that the reply type isn't required
+// to implement `Debug`.
+impl<T> fmt::Debug for ReplyError<T> {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      ReplyError::Cancelled(_) => write!(f, "Cancelled(..)")
+    }
+  }
+}
+
+impl<T> fmt::Display for ReplyError<T> {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      ReplyError::Cancelled(_) => write!(f, "Client stopped waiting for reply")
+    }
+  }
+}
+
+
+/// Returned by
+/// [`StreamReplyContext::finish()`](crate::StreamReplyContext::finish())
+/// when the client has stopped waiting for the reply.
+///
+/// Unlike [`ReplyError`] there's nothing to hand back, since ending a stream
+/// doesn't carry a value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cancelled;
+
+impl std::error::Error for Cancelled {}
+
+impl fmt::Display for Cancelled {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "Client stopped waiting for reply")
+  }
+}
+
 // vim: set ft=rust et sw=2 ts=2 sts=2 cinoptions=2 tw=79 :
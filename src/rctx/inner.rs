@@ -1,217 +1,893 @@
+use std::any::Any;
+use std::cell::UnsafeCell;
+use std::collections::VecDeque;
 use std::future::Future;
 use std::pin::Pin;
-use std::sync::{Arc, Condvar, Mutex};
-use std::task::{Context, Poll};
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::task::{Context, Poll, Waker};
 use std::thread;
+use std::time::Instant;
 
 use crate::rctx::err::Error;
+use crate::timer;
+use crate::waker::{self, AtomicWaker, Waiters};
 
-pub(crate) enum State<I, E> {
-  /// (Still) in queue, waiting to be picked up by the server.
-  Queued,
+// States of a reply slot.
+//
+// The replier moves the slot from `QUEUED` or `WAITING` to `READY` once it
+// has stored the outcome, after which it never touches the outcome again.
+// The originator only accesses the outcome once it has seen the `READY`
+// state, so the state doubles as the lock protecting the outcome.
 
-  /// Was picked up, but (still) waiting for a reply to arrive.
-  Waiting,
+/// (Still) in queue, waiting to be picked up by the server.
+const QUEUED: u8 = 0;
 
-  /// Have a reply, but it wasn't delivered yet.
+/// Was picked up, but (still) waiting for a reply to arrive.
+const WAITING: u8 = 1;
+
+/// The outcome has arrived, but it wasn't delivered yet.
+const READY: u8 = 2;
+
+/// The outcome is being returned to the caller.
+const FINALIZED: u8 = 3;
+
+/// The caller gave up waiting for a reply.  Any reply sent by the server
+/// after this point is discarded.
+const CANCELLED: u8 = 4;
+
+/// Outcome of a request, stored by the replier.
+enum Outcome<I, E> {
+  /// A reply.  For streamed replies this is the final item of the stream.
   Item(I),
 
+  /// A streamed reply has been completed.
+  Done,
+
   /// The application returned an error.
   AppErr(E),
 
-  /// Reply is being returned to caller.
-  Finalized,
-
   /// The server never received the message; it was dropped while in the
   /// queue.  Most likely this means that the message was still in the queue
-  /// when the server was dropped.
-  Aborted,
+  /// when the server was dropped.  The undelivered message is kept so it
+  /// can be handed back to the caller.
+  Aborted(Box<dyn Any + Send>),
 
   /// The message was received by the server, but its reply context was
   /// released before sending back a reply.
-  NoReply
+  NoReply,
+
+  /// The server panicked while processing the message.  Holds the panic
+  /// message, if it is known.
+  Panicked(String)
 }
 
-pub struct InnerReplyContext<I, E> {
-  pub(crate) signal: Arc<Condvar>,
-  pub(crate) data: Arc<Mutex<State<I, E>>>
+impl<I, E> Outcome<I, E> {
+  /// Turn the outcome into the result of a request.
+  fn into_result(self) -> Result<I, Error<E>> {
+    match self {
+      Outcome::Item(msg) => Ok(msg),
+      Outcome::AppErr(err) => Err(Error::App(err)),
+      // Dropped while in queue
+      Outcome::Aborted(msg) => Err(Error::Aborted(msg)),
+      // Dropped after reply context was picked up, but before replying
+      Outcome::NoReply => Err(Error::NoReply),
+      Outcome::Panicked(msg) => Err(Error::Panicked(msg)),
+      Outcome::Done => {
+        // Only streamed replies are completed this way
+        panic!("Unexpected outcome; streamed reply completed");
+      }
+    }
+  }
 }
 
-impl<I: 'static + Send, E> InnerReplyContext<I, E> {
+/// Parts of a reply which may arrive before the outcome.
+struct Pieces<I, P> {
+  /// Items of a streamed reply that haven't been delivered yet.
+  items: VecDeque<I>,
+
+  /// Maximum number of undelivered items, if the stream is bounded.
+  item_cap: Option<usize>,
+
+  /// Tasks on the replier's side waiting for room to add an item to a
+  /// bounded stream.
+  space_waiters: Waiters,
+
+  /// Progress updates that haven't been delivered yet.
+  progress: VecDeque<P>,
+
+  /// Tasks on the replier's side waiting to be told that the originator has
+  /// stopped waiting.
+  cancel_waiters: Waiters
+}
+
+/// Data shared between the originator and the replier.
+///
+/// Everything a plain request/reply needs is handled using atomics; the lock
+/// is only taken by streamed replies, progress updates and cancellation.
+pub(crate) struct Slot<I, E, P> {
+  state: AtomicU8,
+  outcome: UnsafeCell<Option<Outcome<I, E>>>,
+  pieces: Mutex<Pieces<I, P>>,
+
+  /// The originator, whether it's a blocked thread or a task waiting in an
+  /// `async` context, waiting for the state to change.
+  waiter: AtomicWaker
+}
+
+// The outcome is only ever accessed by one side at a time, as determined by
+// the state.
+unsafe impl<I: Send, E: Send, P: Send> Sync for Slot<I, E, P> {}
+
+impl<I, E, P> Slot<I, E, P> {
+  /// Lock the parts of a reply that may arrive before the outcome.
+  ///
+  /// The lock is never held while application code is running, so the
+  /// pieces remain consistent even if a thread panicked while holding it.
+  /// Ignore lock poisoning rather than propagating the panic to the other
+  /// end.
+  fn lock(&self) -> MutexGuard<'_, Pieces<I, P>> {
+    self.pieces.lock().unwrap_or_else(PoisonError::into_inner)
+  }
+
+  /// Store the outcome and signal the originator, provided the slot is in
+  /// one of the states in `from`.  Otherwise the outcome is returned.
+  ///
+  /// Only the replier may call this.
+  fn resolve(
+    &self,
+    outcome: Outcome<I, E>,
+    from: &[u8]
+  ) -> Result<(), Outcome<I, E>> {
+    if let Some(waker) = self.resolve_deferred(outcome, from)? {
+      waker.wake();
+    }
+    Ok(())
+  }
+
+  /// Same as [`Slot::resolve()`], but rather than waking the originator,
+  /// return its waker for the caller to wake.  This allows the replier to
+  /// let go of the slot first, so the originator finds it unused once it
+  /// has been woken.
+  fn resolve_deferred(
+    &self,
+    outcome: Outcome<I, E>,
+    from: &[u8]
+  ) -> Result<Option<Waker>, Outcome<I, E>> {
+    let state = self.state.load(Ordering::Acquire);
+    if !from.contains(&state) {
+      return Err(outcome);
+    }
+
+    // SAFETY: The originator doesn't access the outcome until it sees the
+    // `READY` state.
+    unsafe {
+      *self.outcome.get() = Some(outcome);
+    }
+
+    // The only change the originator can make in the meantime is to cancel.
+    match self.state.compare_exchange(
+      state,
+      READY,
+      Ordering::AcqRel,
+      Ordering::Acquire
+    ) {
+      Ok(_) => Ok(self.waiter.take()),
+      Err(_) => {
+        // SAFETY: The state never reached `READY`, so the originator won't
+        // access the outcome.
+        let outcome = unsafe { (*self.outcome.get()).take() };
+        // Unwrap is okay; the outcome was stored above.
+        Err(outcome.unwrap())
+      }
+    }
+  }
+
+  /// Take the outcome and move to the `FINALIZED` state.
+  ///
+  /// Only the originator may call this, and only once it has seen the
+  /// `READY` state.
+  fn take_outcome(&self) -> Outcome<I, E> {
+    self.state.store(FINALIZED, Ordering::Relaxed);
+
+    // SAFETY: Once the state is `READY` the replier no longer accesses the
+    // outcome.
+    let outcome = unsafe { (*self.outcome.get()).take() };
+    // Unwrap is okay; the replier stores the outcome before moving to the
+    // `READY` state.
+    outcome.unwrap()
+  }
+
+  /// If the final result has arrived, take it and move to the `FINALIZED`
+  /// state.  Returns `None` if still waiting for a reply.
+  fn take_result(&self) -> Option<Result<I, Error<E>>> {
+    match self.state.load(Ordering::Acquire) {
+      QUEUED | WAITING => None,
+      READY => match self.take_outcome() {
+        Outcome::NoReply => {
+          // Leave the slot as it is, so the same error is returned if asked
+          // again.
+          //
+          // SAFETY: As in `take_outcome()`.
+          unsafe {
+            *self.outcome.get() = Some(Outcome::NoReply);
+          }
+          self.state.store(READY, Ordering::Relaxed);
+          Some(Err(Error::NoReply))
+        }
+        outcome => Some(outcome.into_result())
+      },
+      FINALIZED => {
+        // We're *really* in trouble if this happens at this point ..
+        panic!("Unexpected state; finalized");
+      }
+      _ => {
+        // We're *really* in trouble if this happens at this point ..
+        panic!("Unexpected state; cancelled");
+      }
+    }
+  }
+
+  /// Take the next item of a streamed reply.  Returns `Pending` if the
+  /// stream is still waiting for the next item, and `Ready(None)` once the
+  /// stream has ended.
+  ///
+  /// Errors end the stream, as does a reply sent using a non-streaming reply
+  /// context, which is returned as the final item.
+  fn poll_item(&self) -> Poll<Option<Result<I, Error<E>>>> {
+    // Check the state before looking for items.  The replier adds all items
+    // before completing the stream, so if the stream has been completed all
+    // of its items will be found.
+    let state = self.state.load(Ordering::Acquire);
+    let mut pieces = self.lock();
+    if let Some(item) = pieces.items.pop_front() {
+      // Make room for the replier, if it's waiting to add an item to a full
+      // stream.
+      let wakers = pieces.space_waiters.take_all();
+      drop(pieces);
+      for waker in wakers {
+        waker.wake();
+      }
+      return Poll::Ready(Some(Ok(item)));
+    }
+    drop(pieces);
+    match state {
+      QUEUED | WAITING => Poll::Pending,
+      READY => match self.take_outcome() {
+        Outcome::Done => Poll::Ready(None),
+        outcome => Poll::Ready(Some(outcome.into_result()))
+      },
+      _ => Poll::Ready(None)
+    }
+  }
+
+  /// Take the next progress update.  Returns `Pending` if still waiting for
+  /// an update, and `Ready(None)` once the final result has arrived and all
+  /// updates have been taken.
+  fn poll_progress(&self) -> Poll<Option<P>> {
+    // As for items, check the state first.
+    let state = self.state.load(Ordering::Acquire);
+    if let Some(progress) = self.lock().progress.pop_front() {
+      return Poll::Ready(Some(progress));
+    }
+    match state {
+      QUEUED | WAITING => Poll::Pending,
+      _ => Poll::Ready(None)
+    }
+  }
+
+  /// Add an item to a streamed reply and signal the originator.  If the
+  /// stream is bounded and full, register `waker` to be woken once there's
+  /// room for it instead.  `id` identifies the task in the list of tasks
+  /// waiting for room.
+  ///
+  /// Returns `Ready(Err(item))` if the originator has stopped waiting.
+  fn poll_put_item(
+    &self,
+    item: &mut Option<I>,
+    id: &mut Option<usize>,
+    waker: &Waker
+  ) -> Poll<Result<(), I>> {
+    let mut pieces = self.lock();
+    let full = match pieces.item_cap {
+      Some(cap) => pieces.items.len() >= cap,
+      None => false
+    };
+    let cancelled = self.state.load(Ordering::Acquire) == CANCELLED;
+    if full && !cancelled {
+      pieces.space_waiters.register(id, waker);
+      return Poll::Pending;
+    }
+    if let Some(id) = id.take() {
+      pieces.space_waiters.remove(id);
+    }
+
+    // Unwrap is okay; the item is only taken once ready.
+    let item = item.take().unwrap();
+    if cancelled {
+      return Poll::Ready(Err(item));
+    }
+    pieces.items.push_back(item);
+    drop(pieces);
+
+    self.waiter.wake();
+    Poll::Ready(Ok(()))
+  }
+
+  /// Move to the `CANCELLED` state, unless the outcome has already arrived.
+  /// Returns `true` if the slot was cancelled.
+  fn cancel(&self) -> bool {
+    let mut state = self.state.load(Ordering::Acquire);
+    loop {
+      if state != QUEUED && state != WAITING {
+        return false;
+      }
+      // The replier may pick up the request in the meantime.
+      match self.state.compare_exchange_weak(
+        state,
+        CANCELLED,
+        Ordering::AcqRel,
+        Ordering::Acquire
+      ) {
+        Ok(_) => break,
+        Err(actual) => state = actual
+      }
+    }
+
+    // Nobody will collect the pieces that have already arrived; the replier
+    // checks the state while holding the lock, so no more will be added.
+    let mut pieces = self.lock();
+    let items = std::mem::take(&mut pieces.items);
+    let progress = std::mem::take(&mut pieces.progress);
+    let mut wakers = pieces.cancel_waiters.take_all();
+    wakers.append(&mut pieces.space_waiters.take_all());
+    drop(pieces);
+    drop(items);
+    drop(progress);
+
+    for waker in wakers {
+      waker.wake();
+    }
+    true
+  }
+
+  /// Return the slot to the `QUEUED` state, ready to be used for another
+  /// request.  Any buffers are kept, to avoid allocating new ones.
+  fn reset(&mut self) {
+    *self.state.get_mut() = QUEUED;
+    *self.outcome.get_mut() = None;
+    let pieces = self
+      .pieces
+      .get_mut()
+      .unwrap_or_else(PoisonError::into_inner);
+    pieces.items.clear();
+    pieces.item_cap = None;
+    pieces.space_waiters = Waiters::new();
+    pieces.progress.clear();
+    pieces.cancel_waiters = Waiters::new();
+    self.waiter = AtomicWaker::new();
+  }
+
+  /// Block the calling thread until `poll` returns `Ready`.  Gives up and
+  /// returns `None` if `deadline` expires first.
+  fn wait<T, F>(&self, deadline: Option<Instant>, mut poll: F) -> Option<T>
+  where
+    F: FnMut(&Self) -> Poll<T>
+  {
+    let mut waker: Option<Waker> = None;
+    loop {
+      if let Poll::Ready(ret) = poll(self) {
+        break Some(ret);
+      }
+      let waker = waker.get_or_insert_with(waker::current_thread);
+      self.waiter.register(waker);
+
+      // Check again, in case the state changed before the waker was
+      // registered.  Spurious wakeups are harmless; the state is rechecked
+      // regardless.
+      if let Poll::Ready(ret) = poll(self) {
+        break Some(ret);
+      }
+      match deadline {
+        Some(deadline) => {
+          let now = Instant::now();
+          if now >= deadline {
+            break None;
+          }
+          thread::park_timeout(deadline - now);
+        }
+        None => thread::park()
+      }
+    }
+  }
+
+  /// Same as [`Slot::wait()`], but for use in an `async` context.
+  fn poll_wait<T, F>(&self, ctx: &mut Context<'_>, mut poll: F) -> Poll<T>
+  where
+    F: FnMut(&Self) -> Poll<T>
+  {
+    if let ready @ Poll::Ready(_) = poll(self) {
+      return ready;
+    }
+    self.waiter.register(ctx.waker());
+    poll(self)
+  }
+}
+
+fn result_ready<I, E, P>(slot: &Slot<I, E, P>) -> Poll<Result<I, Error<E>>> {
+  match slot.take_result() {
+    Some(ret) => Poll::Ready(ret),
+    None => Poll::Pending
+  }
+}
+
+pub struct InnerReplyContext<I, E, P> {
+  pub(crate) slot: Arc<Slot<I, E, P>>
+}
+
+impl<I: 'static + Send, E, P> InnerReplyContext<I, E, P> {
   /// Create a new reply context in "Queued" state.
   pub(crate) fn new() -> Self {
+    Self::with_state(QUEUED, None)
+  }
+
+  /// Create a new reply context for a streamed reply which holds at most
+  /// `cap` items that haven't been delivered yet.
+  pub(crate) fn bounded(cap: usize) -> Self {
+    Self::with_state(QUEUED, Some(cap))
+  }
+
+  /// Create a reply context that nobody is waiting on, for messages that
+  /// don't expect a reply.  Replies sent through it are handed back.
+  pub(crate) fn cancelled() -> Self {
+    Self::with_state(CANCELLED, None)
+  }
+
+  fn with_state(state: u8, item_cap: Option<usize>) -> Self {
     InnerReplyContext {
-      signal: Arc::new(Condvar::new()),
-      data: Arc::new(Mutex::new(State::Queued))
+      slot: Arc::new(Slot {
+        state: AtomicU8::new(state),
+        outcome: UnsafeCell::new(None),
+        pieces: Mutex::new(Pieces {
+          items: VecDeque::new(),
+          item_cap,
+          space_waiters: Waiters::new(),
+          progress: VecDeque::new(),
+          cancel_waiters: Waiters::new()
+        }),
+        waiter: AtomicWaker::new()
+      })
     }
   }
 
-  /// Store a reply and signal the originator that a reply has arrived.
-  pub fn put(&self, item: I) {
-    let mut mg = self.data.lock().unwrap();
-    *mg = State::Item(item);
-    drop(mg);
 
-    self.signal.notify_one();
+  /// Store a reply and signal the originator that a reply has arrived.
+  ///
+  /// The reply context is released before the originator is woken, so that
+  /// a client reusing its reply slot is able to do so as soon as it has
+  /// received the reply.
+  ///
+  /// If the originator has stopped waiting for the reply the item is
+  /// returned.
+  pub fn put(self, item: I) -> Result<(), I> {
+    match self
+      .slot
+      .resolve_deferred(Outcome::Item(item), &[QUEUED, WAITING])
+    {
+      Ok(waker) => {
+        self.release_and_wake(waker);
+        Ok(())
+      }
+      Err(Outcome::Item(item)) => Err(item),
+      Err(_) => Ok(())
+    }
   }
 
   /// Store an error and signal the originator that a result has arrived.
-  pub fn fail(&self, err: E) {
-    let mut mg = self.data.lock().unwrap();
-    *mg = State::AppErr(err);
-    drop(mg);
+  /// As with [`InnerReplyContext::put()`], the reply context is released
+  /// before the originator is woken.
+  ///
+  /// If the originator has stopped waiting for the reply the error is
+  /// returned.
+  pub fn fail(self, err: E) -> Result<(), E> {
+    match self
+      .slot
+      .resolve_deferred(Outcome::AppErr(err), &[QUEUED, WAITING])
+    {
+      Ok(waker) => {
+        self.release_and_wake(waker);
+        Ok(())
+      }
+      Err(Outcome::AppErr(err)) => Err(err),
+      Err(_) => Ok(())
+    }
+  }
+
+  /// Add an item to a streamed reply and signal the originator that an item
+  /// has arrived.  If the stream is bounded and full, block until the
+  /// originator has taken an item.
+  ///
+  /// If the originator has stopped waiting for the reply the item is
+  /// returned.
+  pub(crate) fn put_item(&self, item: I) -> Result<(), I> {
+    let waker = waker::current_thread();
+    let mut item = Some(item);
+    let mut id = None;
+    loop {
+      if let Poll::Ready(res) =
+        self.slot.poll_put_item(&mut item, &mut id, &waker)
+      {
+        break res;
+      }
+      // Spurious wakeups are harmless; the stream is rechecked regardless.
+      thread::park();
+    }
+  }
+
+  /// Same as [`InnerReplyContext::put_item()`], but returns a `Future` for
+  /// use in an `async` context.
+  pub(crate) fn aput_item(&self, item: I) -> PutItemFuture<I, E, P> {
+    PutItemFuture {
+      slot: Arc::clone(&self.slot),
+      item: Some(item),
+      id: None
+    }
+  }
+
+  /// Complete a streamed reply and signal the originator.  As with
+  /// [`InnerReplyContext::put()`], the reply context is released before the
+  /// originator is woken.
+  ///
+  /// Returns `Err(())` if the originator has stopped waiting for the reply.
+  pub(crate) fn finish(self) -> Result<(), ()> {
+    match self
+      .slot
+      .resolve_deferred(Outcome::Done, &[QUEUED, WAITING])
+    {
+      Ok(waker) => {
+        self.release_and_wake(waker);
+        Ok(())
+      }
+      Err(_) => Err(())
+    }
+  }
+
+  /// Send a progress update and signal the originator.
+  ///
+  /// If the originator has stopped waiting for the reply the update is
+  /// returned.
+  pub(crate) fn put_progress(&self, progress: P) -> Result<(), P> {
+    let mut pieces = self.slot.lock();
+    if self.slot.state.load(Ordering::Acquire) == CANCELLED {
+      return Err(progress);
+    }
+    pieces.progress.push_back(progress);
+    drop(pieces);
 
-    self.signal.notify_one();
+    self.slot.waiter.wake();
+    Ok(())
+  }
+
+  /// Retreive the next progress update, waiting for it to arrive if needed.
+  /// Returns `None` once the final result has arrived, and all the updates
+  /// sent before it have been retreived.
+  pub(crate) fn next_progress(&self) -> Option<P> {
+    // Unwrap is okay; there's no deadline.
+    self.slot.wait(None, Slot::poll_progress).unwrap()
+  }
+
+  /// Retreive the next progress update if one has arrived, without waiting
+  /// for it.
+  pub(crate) fn try_progress(&self) -> Option<P> {
+    self.slot.lock().progress.pop_front()
+  }
+
+  /// Same as [`InnerReplyContext::next_progress()`], but for use in an
+  /// `async` context.
+  pub(crate) fn poll_next_progress(
+    &self,
+    ctx: &mut Context<'_>
+  ) -> Poll<Option<P>> {
+    self.slot.poll_wait(ctx, Slot::poll_progress)
+  }
+
+  /// Retreive the next item of a streamed reply, waiting for it to arrive if
+  /// needed.  Returns `None` once the stream has ended.
+  pub(crate) fn next_item(&self) -> Option<Result<I, Error<E>>> {
+    // Unwrap is okay; there's no deadline.
+    self.slot.wait(None, Slot::poll_item).unwrap()
+  }
+
+  /// Same as [`InnerReplyContext::next_item()`], but for use in an `async`
+  /// context.
+  pub(crate) fn poll_next_item(
+    &self,
+    ctx: &mut Context<'_>
+  ) -> Poll<Option<Result<I, Error<E>>>> {
+    self.slot.poll_wait(ctx, Slot::poll_item)
   }
 
   /// Retreive reply.  If a reply has not arrived yet then enter a loop that
   /// waits for a reply to arrive.
   pub fn get(&self) -> Result<I, Error<E>> {
-    let mut mg = self.data.lock().unwrap();
-
-    let ret = loop {
-      match &*mg {
-        State::Queued | State::Waiting => {
-          // Still waiting for server to report back with data
-          mg = self.signal.wait(mg).unwrap();
-          continue;
-        }
-        State::Item(_msg) => {
-          // Set Finalized state and return item
-          if let State::Item(msg) =
-            std::mem::replace(&mut *mg, State::Finalized)
-          {
-            break Ok(msg);
-          } else {
-            // We're *really* in trouble if this happens ..
-            panic!("Unexpected state; not State::Item()");
-          }
-        }
-        State::AppErr(_err) => {
-          // Set Finalized state and return error
-          if let State::AppErr(err) =
-            std::mem::replace(&mut *mg, State::Finalized)
-          {
-            break Err(Error::App(err));
-          } else {
-            // We're *really* in trouble if this happens ..
-            panic!("Unexpected state; not State::AppErr()");
-          }
-        }
-        State::Finalized => {
-          // We're *really* in trouble if this happens at this point ..
-          panic!("Unexpected state State::Finalized");
-        }
-        State::Aborted => {
-          // Dropped while in queue
-          return Err(Error::Aborted);
-        }
-        State::NoReply => {
-          // Dropped after reply context was picked up, but before replying
-          return Err(Error::NoReply);
-        }
+    self.get_until(None)
+  }
+
+  /// Same as [`InnerReplyContext::get()`], but give up and return
+  /// `Error::Timeout` if no reply has arrived by `deadline`.
+  ///
+  /// On timeout the reply context is put in the `Cancelled` state so that a
+  /// late reply from the server is discarded rather than stored.
+  pub fn get_deadline(&self, deadline: Instant) -> Result<I, Error<E>> {
+    self.get_until(Some(deadline))
+  }
+
+  fn get_until(&self, deadline: Option<Instant>) -> Result<I, Error<E>> {
+    loop {
+      if let Some(ret) = self.slot.wait(deadline, result_ready) {
+        break ret;
       }
-    };
-    drop(mg);
 
-    ret
+      // The deadline expired; give up, unless the reply arrived at the last
+      // moment.
+      if self.slot.cancel() {
+        break Err(Error::Timeout);
+      }
+    }
+  }
+
+  /// Retreive reply if it has arrived, without waiting for it.
+  pub fn try_get(&self) -> Option<Result<I, Error<E>>> {
+    self.slot.take_result()
   }
 
-  pub fn aget(&self) -> WaitReplyFuture<I, E> {
-    WaitReplyFuture::new(self)
+  pub fn aget(&self) -> WaitReplyFuture<I, E, P> {
+    WaitReplyFuture::new(self, None)
+  }
+
+  /// Same as [`InnerReplyContext::aget()`], but the returned future resolves
+  /// to `Error::Timeout` if no reply has arrived by `deadline`.
+  pub fn aget_deadline(&self, deadline: Instant) -> WaitReplyFuture<I, E, P> {
+    WaitReplyFuture::new(self, Some(deadline))
   }
 }
 
-impl<I, E> Clone for InnerReplyContext<I, E> {
-  fn clone(&self) -> Self {
-    InnerReplyContext {
-      signal: Arc::clone(&self.signal),
-      data: Arc::clone(&self.data)
+impl<I, E, P> InnerReplyContext<I, E, P> {
+  /// Prepare the reply context to be used for another request.
+  ///
+  /// This is only possible once nobody else holds a reference to the reply
+  /// context;  i.e. the server has dropped its end, and there are no
+  /// futures waiting on it.  Returns `false` if this isn't the case, in which
+  /// case the reply context is left as it is.
+  pub(crate) fn reset(&mut self) -> bool {
+    // Once the outcome has arrived the replier has nothing left to do but
+    // drop its reference, which it may not have gotten around to if the
+    // originator saw the outcome without waiting to be woken.  Give it the
+    // chance to rather than allocate a new slot.
+    if matches!(self.slot.state.load(Ordering::Acquire), READY | FINALIZED) {
+      while Arc::strong_count(&self.slot) > 1 {
+        thread::yield_now();
+      }
+    }
+    match Arc::get_mut(&mut self.slot) {
+      Some(slot) => {
+        slot.reset();
+        true
+      }
+      None => false
     }
   }
-}
 
-impl<I, E> Drop for InnerReplyContext<I, E> {
-  /// If the reply context never left the server queue before being destroyed
-  /// it means that the server has died.  Signal this to the original caller
-  /// waiting for a reply.
-  fn drop(&mut self) {
-    let mut do_signal: bool = false;
-    let mut mg = self.data.lock().unwrap();
-    match *mg {
-      State::Queued => {
-        *mg = State::Aborted;
-        do_signal = true;
+  /// Drop the replier's reference to the slot, and then wake the originator.
+  fn release_and_wake(self, waker: Option<Waker>) {
+    drop(self);
+    if let Some(waker) = waker {
+      waker.wake();
+    }
+  }
+
+  /// Mark the reply context as aborted, storing the message that never
+  /// reached the server, and signal the originator.
+  pub(crate) fn abort(&self, msg: Box<dyn Any + Send>) {
+    let _ = self.slot.resolve(Outcome::Aborted(msg), &[QUEUED]);
+  }
+
+  /// The replier has picked up the message; change the state from `Queued`
+  /// to `Waiting`.
+  pub(crate) fn pick_up(&self) {
+    match self.slot.state.compare_exchange(
+      QUEUED,
+      WAITING,
+      Ordering::AcqRel,
+      Ordering::Acquire
+    ) {
+      Ok(_) => {}
+      Err(CANCELLED) => {
+        // The caller gave up while the message was queued; leave the state
+        // as-is so the reply is discarded.
+      }
+      Err(_) => {
+        // Should never happen
+        panic!("Unexpected node state.");
       }
-      _ => {}
     }
-    drop(mg);
-    if do_signal {
-      self.signal.notify_one();
+  }
+
+  /// The public reply context was dropped.  If it's still waiting for a
+  /// reply, report back to the originator that it should expect no reply,
+  /// or that the server panicked if the replier's thread is unwinding.
+  pub(crate) fn release(&self) {
+    if thread::panicking() {
+      self.panicked(String::from("Server panicked while processing request"));
+      return;
+    }
+    let _ = self.slot.resolve(Outcome::NoReply, &[WAITING]);
+  }
+
+  /// Report to the originator that the server panicked while processing the
+  /// message.
+  pub(crate) fn panicked(&self, msg: String) {
+    let _ = self.slot.resolve(Outcome::Panicked(msg), &[WAITING]);
+  }
+
+  /// Stop waiting for a reply.  If it hasn't arrived yet, then mark the reply
+  /// context as cancelled so that the server can skip the request, or
+  /// discard its reply.
+  pub(crate) fn cancel(&self) {
+    self.slot.cancel();
+  }
+
+  /// Returns `true` if the originator has stopped waiting for a reply.
+  pub(crate) fn is_cancelled(&self) -> bool {
+    self.slot.state.load(Ordering::Acquire) == CANCELLED
+  }
+
+  /// Returns a future that resolves once the originator has stopped waiting
+  /// for a reply.
+  pub(crate) fn acancelled(&self) -> WaitCancelFuture<I, E, P> {
+    WaitCancelFuture {
+      slot: Arc::clone(&self.slot),
+      id: None
+    }
+  }
+}
+
+impl<I, E, P> Clone for InnerReplyContext<I, E, P> {
+  fn clone(&self) -> Self {
+    InnerReplyContext {
+      slot: Arc::clone(&self.slot)
     }
   }
 }
 
 
-pub struct WaitReplyFuture<I, E> {
-  signal: Arc<Condvar>,
-  data: Arc<Mutex<State<I, E>>>
+pub struct WaitReplyFuture<I, E, P> {
+  slot: Arc<Slot<I, E, P>>,
+  deadline: Option<Instant>,
+
+  /// Registration with the timer that wakes the task when the deadline
+  /// expires.
+  timer: Option<timer::Registration>
 }
 
-impl<I, E> WaitReplyFuture<I, E> {
-  fn new(irctx: &InnerReplyContext<I, E>) -> Self {
+impl<I, E, P> WaitReplyFuture<I, E, P> {
+  fn new(
+    irctx: &InnerReplyContext<I, E, P>,
+    deadline: Option<Instant>
+  ) -> Self {
     WaitReplyFuture {
-      signal: Arc::clone(&irctx.signal),
-      data: Arc::clone(&irctx.data)
+      slot: Arc::clone(&irctx.slot),
+      deadline,
+      timer: None
     }
   }
 }
 
-impl<I: 'static + Send, E: 'static + Send> Future for WaitReplyFuture<I, E> {
+impl<I: 'static + Send, E: 'static + Send, P> Future
+  for WaitReplyFuture<I, E, P>
+{
   type Output = Result<I, Error<E>>;
   fn poll(self: Pin<&mut Self>, ctx: &mut Context<'_>) -> Poll<Self::Output> {
-    let mut state = self.data.lock().unwrap();
-    match &*state {
-      State::Queued | State::Waiting => {
-        let waker = ctx.waker().clone();
-        let data = Arc::clone(&self.data);
-        let signal = Arc::clone(&self.signal);
-        thread::spawn(move || {
-          let mut istate = data.lock().unwrap();
-          if let State::Waiting = *istate {
-            istate = signal.wait(istate).unwrap();
-          }
-          drop(istate);
-          waker.wake();
-        });
-        drop(state);
-        Poll::Pending
-      }
-      State::Item(_msg) => {
-        if let State::Item(msg) =
-          std::mem::replace(&mut *state, State::Finalized)
-        {
-          Poll::Ready(Ok(msg))
-        } else {
-          // We're *really* in trouble if this happens ..
-          panic!("Unexpected state; not State::Item()");
-        }
-      }
-      State::AppErr(_err) => {
-        if let State::AppErr(err) =
-          std::mem::replace(&mut *state, State::Finalized)
-        {
-          Poll::Ready(Err(Error::App(err)))
-        } else {
-          // We're *really* in trouble if this happens ..
-          panic!("Unexpected state; not State::App()");
+    let this = self.get_mut();
+    if let Some(ret) = this.slot.take_result() {
+      return Poll::Ready(ret);
+    }
+
+    if let Some(deadline) = this.deadline {
+      if Instant::now() >= deadline {
+        if this.slot.cancel() {
+          return Poll::Ready(Err(Error::Timeout));
         }
+        // The reply arrived at the last moment.
+        return result_ready(&this.slot);
       }
-      State::Finalized => {
-        panic!("Unexpected state");
+      match &this.timer {
+        Some(reg) => reg.update(ctx.waker()),
+        None => this.timer = Some(timer::wake_at(deadline, ctx.waker()))
       }
-      State::Aborted => Poll::Ready(Err(Error::Aborted)),
-      State::NoReply => Poll::Ready(Err(Error::NoReply))
+    }
+
+    // Register the task's waker, replacing any old one, so it will be woken
+    // by whatever changes the state.
+    this.slot.poll_wait(ctx, result_ready)
+  }
+}
+
+impl<I, E, P> Drop for WaitReplyFuture<I, E, P> {
+  /// If the future is dropped before a reply has arrived then nobody is
+  /// waiting for it any more.  Mark the reply context as cancelled so that
+  /// the server can skip the request, or discard its reply.
+  fn drop(&mut self) {
+    self.slot.cancel();
+  }
+}
+
+
+pub struct WaitCancelFuture<I, E, P> {
+  slot: Arc<Slot<I, E, P>>,
+
+  /// Identifier of the task's entry in the slot's list of cancellation
+  /// waiters, once it has been registered.
+  id: Option<usize>
+}
+
+impl<I, E, P> Future for WaitCancelFuture<I, E, P> {
+  type Output = ();
+  fn poll(self: Pin<&mut Self>, ctx: &mut Context<'_>) -> Poll<Self::Output> {
+    let this = self.get_mut();
+    if this.slot.state.load(Ordering::Acquire) == CANCELLED {
+      return Poll::Ready(());
+    }
+
+    // `Slot::cancel()` changes the state before taking the lock to wake the
+    // waiters, so checking the state with the lock held won't miss a wakeup.
+    let mut pieces = this.slot.lock();
+    if this.slot.state.load(Ordering::Acquire) == CANCELLED {
+      return Poll::Ready(());
+    }
+    pieces.cancel_waiters.register(&mut this.id, ctx.waker());
+    Poll::Pending
+  }
+}
+
+impl<I, E, P> Drop for WaitCancelFuture<I, E, P> {
+  /// Remove the task from the list of cancellation waiters, so the slot
+  /// doesn't hold on to wakers of tasks that are no longer waiting.
+  fn drop(&mut self) {
+    if let Some(id) = self.id.take() {
+      self.slot.lock().cancel_waiters.remove(id);
+    }
+  }
+}
+
+
+pub struct PutItemFuture<I, E, P> {
+  slot: Arc<Slot<I, E, P>>,
+  item: Option<I>,
+
+  /// Identifier of the task's entry in the slot's list of tasks waiting for
+  /// room in the stream, once it has been registered.
+  id: Option<usize>
+}
+
+// The item is never pinned.
+impl<I, E, P> Unpin for PutItemFuture<I, E, P> {}
+
+impl<I, E, P> Future for PutItemFuture<I, E, P> {
+  type Output = Result<(), I>;
+  fn poll(self: Pin<&mut Self>, ctx: &mut Context<'_>) -> Poll<Self::Output> {
+    let this = self.get_mut();
+    this
+      .slot
+      .poll_put_item(&mut this.item, &mut this.id, ctx.waker())
+  }
+}
+
+impl<I, E, P> Drop for PutItemFuture<I, E, P> {
+  /// Stop waiting for room in the stream.
+  fn drop(&mut self) {
+    if let Some(id) = self.id.take() {
+      self.slot.lock().space_waiters.remove(id);
     }
   }
 }
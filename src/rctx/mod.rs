@@ -9,10 +9,13 @@ mod err;
 mod inner;
 
 pub mod public;
+pub mod stream;
 
 pub(crate) use err::Error;
-pub(crate) use inner::InnerReplyContext;
+pub(crate) use inner::{InnerReplyContext, WaitReplyFuture};
 
+pub use err::{Cancelled, ReplyError};
 pub use public::ReplyContext;
+pub use stream::StreamReplyContext;
 
 // vim: set ft=rust et sw=2 ts=2 sts=2 cinoptions=2 tw=79 :
@@ -1,5 +1,8 @@
-use crate::rctx::err::Error;
-use crate::rctx::inner::State;
+use std::any::Any;
+use std::panic::{self, UnwindSafe};
+use std::thread;
+
+use crate::rctx::err::ReplyError;
 use crate::rctx::InnerReplyContext;
 
 /// Public-facing sender part of the `ReplyContext` object.
@@ -7,12 +10,12 @@ use crate::rctx::InnerReplyContext;
 /// This is safe to pass to applications which are meant to only be able to put
 /// a value through the `ReplyContext` channel, but not extract the value from
 /// it.
-pub struct ReplyContext<I, E> {
-  inner: InnerReplyContext<I, E>,
+pub struct ReplyContext<I, E, P = ()> {
+  inner: InnerReplyContext<I, E, P>,
   did_handover: bool
 }
 
-impl<I: 'static + Send, E> ReplyContext<I, E> {
+impl<I: 'static + Send, E, P> ReplyContext<I, E, P> {
   /// Send a reply back to originating client.
   ///
   /// # Example
@@ -23,7 +26,7 @@ impl<I: 'static + Send, E> ReplyContext<I, E> {
   /// fn main() {
   ///   let (server, client) = channel::<String, String, ()>();
   ///   let server_thread = thread::spawn(move || {
-  ///     let (data, rctx) = server.wait();
+  ///     let (data, rctx) = server.wait().unwrap();
   ///     let reply = format!("Hello, {}!", data);
   ///     rctx.reply(reply).unwrap();
   ///   });
@@ -36,12 +39,11 @@ impl<I: 'static + Send, E> ReplyContext<I, E> {
   ///
   /// # Semantics
   /// This call is safe to make after the server context has been released.
-  pub fn reply(mut self, data: I) -> Result<(), Error<E>> {
-    self.inner.put(data);
-
-    self.did_handover = true;
-
-    Ok(())
+  ///
+  /// If the client has stopped waiting for the reply, for instance because
+  /// it timed out, the reply is returned in [`ReplyError::Cancelled`].
+  pub fn reply(self, data: I) -> Result<(), ReplyError<I>> {
+    self.hand_over().put(data).map_err(ReplyError::Cancelled)
   }
 
   /// Return an error to originating client.
@@ -62,7 +64,7 @@ impl<I: 'static + Send, E> ReplyContext<I, E> {
   /// fn main() {
   ///   let (server, client) = channel::<String, String, MyError>();
   ///   let server_thread = thread::spawn(move || {
-  ///     let (_, rctx) = server.wait();
+  ///     let (_, rctx) = server.wait().unwrap();
   ///     rctx.fail(MyError::SomeError("failed".to_string())).unwrap();
   ///   });
   ///   let msg = String::from("Client");
@@ -81,61 +83,212 @@ impl<I: 'static + Send, E> ReplyContext<I, E> {
   ///
   /// # Semantics
   /// This call is safe to make after the server context has been released.
-  pub fn fail(mut self, err: E) -> Result<(), Error<E>> {
-    self.inner.fail(err);
+  ///
+  /// If the client has stopped waiting for the reply, for instance because
+  /// it timed out, the error is returned in [`ReplyError::Cancelled`].
+  pub fn fail(self, err: E) -> Result<(), ReplyError<E>> {
+    self.hand_over().fail(err).map_err(ReplyError::Cancelled)
+  }
 
-    self.did_handover = true;
+  /// Send a progress update to the originating client, without completing
+  /// the request.
+  ///
+  /// Updates are buffered until the client picks them up, so this never
+  /// blocks.  The client receives them, in the order they were sent, before
+  /// the final reply.  Clients that don't look for progress updates silently
+  /// discard them.
+  ///
+  /// # Example
+  /// ```
+  /// use std::thread;
+  /// use ump::progress_channel;
+  ///
+  /// let (server, client) = progress_channel::<u32, u32, (), u32>();
+  /// let server_thread = thread::spawn(move || {
+  ///   let (n, rctx) = server.wait().unwrap();
+  ///   for percent in [25, 50, 75] {
+  ///     rctx.progress(percent).unwrap();
+  ///   }
+  ///   rctx.reply(n * 2).unwrap();
+  /// });
+  /// let mut updates = Vec::new();
+  /// let reply = client.send_with_progress(21, |p| updates.push(p)).unwrap();
+  /// assert_eq!(reply, 42);
+  /// assert_eq!(updates, vec![25, 50, 75]);
+  /// server_thread.join().unwrap();
+  /// ```
+  ///
+  /// # Semantics
+  /// If the client has stopped waiting for the reply, for instance because
+  /// it timed out, the update is returned in [`ReplyError::Cancelled`].
+  pub fn progress(&self, progress: P) -> Result<(), ReplyError<P>> {
+    self
+      .inner
+      .put_progress(progress)
+      .map_err(ReplyError::Cancelled)
+  }
 
-    Ok(())
+  /// Run a handler and send its result back to the originating client.
+  ///
+  /// If `f` returns `Ok(reply)` the reply is sent, and if it returns
+  /// `Err(err)` the client receives `Error::App(err)`.  If `f` panics, the
+  /// panic is caught and the client receives `Error::ServerPanicked` holding
+  /// the panic message.  The panic payload is then returned to the caller,
+  /// which can choose to carry on serving requests or to resume the panic
+  /// using [`std::panic::resume_unwind()`].
+  ///
+  /// If the client has stopped waiting for a reply, the handler's result is
+  /// discarded.  Use [`ReplyContext::reply()`] or [`ReplyContext::fail()`]
+  /// directly to get hold of undelivered results.
+  ///
+  /// # Example
+  /// ```
+  /// use std::thread;
+  /// use ump::{channel, Error};
+  ///
+  /// fn main() {
+  ///   let (server, client) = channel::<u32, u32, ()>();
+  ///   let server_thread = thread::spawn(move || {
+  ///     let (data, rctx) = server.wait().unwrap();
+  ///     let res = rctx.run(move || {
+  ///       if data == 0 {
+  ///         panic!("Division by zero");
+  ///       }
+  ///       Ok(100 / data)
+  ///     });
+  ///     assert!(res.is_err());
+  ///   });
+  ///   match client.send(0) {
+  ///     Err(Error::ServerPanicked(msg)) => {
+  ///       assert_eq!(msg, "Division by zero");
+  ///     }
+  ///     _ => panic!("Unexpected return value")
+  ///   }
+  ///   server_thread.join().unwrap();
+  /// }
+  /// ```
+  pub fn run<F>(self, f: F) -> thread::Result<()>
+  where
+    F: FnOnce() -> Result<I, E> + UnwindSafe
+  {
+    match panic::catch_unwind(f) {
+      Ok(Ok(reply)) => {
+        let _ = self.hand_over().put(reply);
+        Ok(())
+      }
+      Ok(Err(err)) => {
+        let _ = self.hand_over().fail(err);
+        Ok(())
+      }
+      Err(payload) => {
+        self.hand_over().panicked(panic_message(&*payload));
+        Err(payload)
+      }
+    }
   }
 }
 
-impl<I, E> Drop for ReplyContext<I, E> {
+/// Extract the message from a panic payload.  `panic!()` produces either a
+/// `&'static str` or a `String`, depending on whether it was formatted.
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+  if let Some(msg) = payload.downcast_ref::<&str>() {
+    String::from(*msg)
+  } else if let Some(msg) = payload.downcast_ref::<String>() {
+    msg.clone()
+  } else {
+    String::from("Unknown panic payload")
+  }
+}
+
+impl<I, E, P> ReplyContext<I, E, P> {
+  /// Returns `true` if the client that sent the message has stopped waiting
+  /// for a reply, for instance because it timed out or because its `async`
+  /// call was dropped.
+  ///
+  /// Any reply sent after the client has stopped waiting is discarded, so
+  /// long-running handlers can use this to abandon work early.
+  pub fn is_cancelled(&self) -> bool {
+    self.inner.is_cancelled()
+  }
+
+  /// Wait, in an `async` context, for the client to stop waiting for a reply.
+  ///
+  /// This is intended to be raced against the processing of the request, so
+  /// that the work can be abandoned as soon as nobody is interested in its
+  /// result.  If the client never stops waiting the returned future never
+  /// resolves.
+  ///
+  /// # Example
+  /// ```
+  /// use std::thread;
+  /// use std::time::Duration;
+  /// use ump::{channel, Error};
+  ///
+  /// let (server, client) = channel::<String, String, ()>();
+  /// let server_thread = thread::spawn(move || {
+  ///   let (_, rctx) = server.wait().unwrap();
+  ///   tokio::runtime::Runtime::new().unwrap().block_on(async {
+  ///     tokio::select! {
+  ///       _ = rctx.cancelled() => {
+  ///         // The client gave up; stop processing
+  ///       }
+  ///       _ = tokio::time::sleep(Duration::from_secs(60)) => {
+  ///         panic!("Expected the client to give up");
+  ///       }
+  ///     }
+  ///   });
+  /// });
+  /// let msg = String::from("Client");
+  /// let reply = client.send_timeout(msg, Duration::from_millis(100));
+  /// assert!(matches!(reply, Err(Error::Timeout)));
+  /// server_thread.join().unwrap();
+  /// ```
+  pub async fn cancelled(&self) {
+    self.inner.acancelled().await
+  }
+}
+
+impl<I, E, P> Drop for ReplyContext<I, E, P> {
   /// If the reply context is dropped while still waiting for a reply then
-  /// report back to the caller that it should expect no reply.
+  /// report back to the caller that it should expect no reply.  If it is
+  /// dropped because the thread holding it is unwinding, report that the
+  /// server panicked instead.
   fn drop(&mut self) {
-    if self.did_handover == false {
-      let mut do_signal: bool = false;
-      let mut mg = self.inner.data.lock().unwrap();
-      match *mg {
-        State::Waiting => {
-          *mg = State::NoReply;
-          do_signal = true;
-        }
-        _ => {}
-      }
-      drop(mg);
-      if do_signal {
-        self.inner.signal.notify_one();
-      }
+    if !self.did_handover {
+      self.inner.release();
+    }
+  }
+}
+
+impl<I, E, P> ReplyContext<I, E, P> {
+  /// Take the internal reply context, to deliver the outcome through it.
+  /// Once this has been called, dropping the reply context no longer
+  /// reports that there will be no reply.
+  fn hand_over(mut self) -> InnerReplyContext<I, E, P> {
+    self.did_handover = true;
+    self.inner.clone()
+  }
+
+  /// Create a public reply context from an internal reply context that has
+  /// already been picked up.
+  pub(crate) fn picked_up(inner: InnerReplyContext<I, E, P>) -> Self {
+    ReplyContext {
+      inner,
+      did_handover: false
     }
   }
 }
 
-impl<I, E> From<InnerReplyContext<I, E>> for ReplyContext<I, E> {
+impl<I, E, P> From<InnerReplyContext<I, E, P>> for ReplyContext<I, E, P> {
   /// Transform an internal reply context into a public one and change the
   /// state from Queued to Waiting to signal that the node has left the
   /// queue.
-  fn from(inner: InnerReplyContext<I, E>) -> Self {
+  fn from(inner: InnerReplyContext<I, E, P>) -> Self {
     // Switch state from "Queued" to "Waiting", to mark that the reply context
     // has been "picked up".
-    let mut mg = inner.data.lock().unwrap();
-    match *mg {
-      State::Queued => {
-        *mg = State::Waiting;
-        drop(mg);
-      }
-      _ => {
-        // Should never happen
-        drop(mg);
-        panic!("Unexpected node state.");
-      }
-    }
+    inner.pick_up();
 
-    ReplyContext {
-      inner: inner.clone(),
-      did_handover: false
-    }
+    ReplyContext::picked_up(inner)
   }
 }
 
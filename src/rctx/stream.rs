@@ -0,0 +1,129 @@
+use crate::rctx::err::{Cancelled, ReplyError};
+use crate::rctx::{InnerReplyContext, ReplyContext};
+
+/// Reply context used to send a reply back to the client as a stream of
+/// items.
+///
+/// Received by servers, through
+/// [`Incoming::Stream`](crate::Incoming::Stream), for requests sent using
+/// [`Client::send_stream()`](crate::Client::send_stream()).
+///
+/// The server sends any number of items using
+/// [`StreamReplyContext::send_item()`] and then ends the stream using either
+/// [`StreamReplyContext::finish()`] or [`StreamReplyContext::fail()`].  If the
+/// stream reply context is dropped before the stream has been ended, the
+/// client receives `Error::NoReply` after the items that were sent.
+pub struct StreamReplyContext<I, E, P = ()> {
+  inner: InnerReplyContext<I, E, P>,
+  did_handover: bool
+}
+
+impl<I: 'static + Send, E, P> StreamReplyContext<I, E, P> {
+  /// Send an item to the originating client.
+  ///
+  /// Items are buffered until the client picks them up.  For streams
+  /// requested using [`Client::send_stream()`](crate::Client::send_stream())
+  /// the buffer is unbounded and this never blocks, so there's no
+  /// backpressure:  A server that produces items faster than the client
+  /// consumes them grows the buffer without limit.  For streams requested
+  /// using
+  /// [`Client::send_stream_bounded()`](crate::Client::send_stream_bounded())
+  /// this blocks while the buffer is full.
+  ///
+  /// If the client has stopped waiting for the reply, for instance because it
+  /// dropped its [`ReplyStream`](crate::ReplyStream), the item is returned in
+  /// [`ReplyError::Cancelled`].
+  pub fn send_item(&self, item: I) -> Result<(), ReplyError<I>> {
+    self.inner.put_item(item).map_err(ReplyError::Cancelled)
+  }
+
+  /// Same as [`StreamReplyContext::send_item()`], but for use in an `async`
+  /// context.  Waits rather than blocks while a bounded stream's buffer is
+  /// full.
+  pub async fn async_send_item(&self, item: I) -> Result<(), ReplyError<I>> {
+    self
+      .inner
+      .aput_item(item)
+      .await
+      .map_err(ReplyError::Cancelled)
+  }
+
+  /// End the stream successfully.
+  ///
+  /// If the client has stopped waiting for the reply [`Cancelled`] is
+  /// returned.
+  pub fn finish(self) -> Result<(), Cancelled> {
+    self.hand_over().finish().map_err(|()| Cancelled)
+  }
+
+  /// End the stream with an error.  The client receives the error, wrapped
+  /// in an `Error::App(err)`, after all the items that were sent.
+  ///
+  /// If the client has stopped waiting for the reply, the error is returned
+  /// in [`ReplyError::Cancelled`].
+  pub fn fail(self, err: E) -> Result<(), ReplyError<E>> {
+    self.hand_over().fail(err).map_err(ReplyError::Cancelled)
+  }
+}
+
+impl<I, E, P> StreamReplyContext<I, E, P> {
+  /// Returns `true` if the client that sent the message has stopped waiting
+  /// for the reply.
+  ///
+  /// See [`ReplyContext::is_cancelled()`](crate::ReplyContext::is_cancelled).
+  pub fn is_cancelled(&self) -> bool {
+    self.inner.is_cancelled()
+  }
+
+  /// Wait, in an `async` context, for the client to stop waiting for the
+  /// reply.
+  ///
+  /// See [`ReplyContext::cancelled()`](crate::ReplyContext::cancelled).
+  pub async fn cancelled(&self) {
+    self.inner.acancelled().await
+  }
+}
+
+impl<I, E, P> StreamReplyContext<I, E, P> {
+  /// Take the internal reply context, to end the stream through it.  Once
+  /// this has been called, dropping the stream reply context no longer
+  /// reports that there will be no more items.
+  fn hand_over(mut self) -> InnerReplyContext<I, E, P> {
+    self.did_handover = true;
+    self.inner.clone()
+  }
+
+  /// Turn the stream reply context into an ordinary reply context, whose
+  /// reply is delivered as the only item of the stream.
+  pub(crate) fn into_single(self) -> ReplyContext<I, E, P> {
+    ReplyContext::picked_up(self.hand_over())
+  }
+}
+
+impl<I, E, P> Drop for StreamReplyContext<I, E, P> {
+  /// If the stream reply context is dropped before the stream was ended then
+  /// report back to the caller that it should expect no more items.
+  fn drop(&mut self) {
+    if !self.did_handover {
+      self.inner.release();
+    }
+  }
+}
+
+impl<I, E, P> From<InnerReplyContext<I, E, P>>
+  for StreamReplyContext<I, E, P>
+{
+  /// Transform an internal reply context into a public one and change the
+  /// state from Queued to Waiting to signal that the node has left the
+  /// queue.
+  fn from(inner: InnerReplyContext<I, E, P>) -> Self {
+    inner.pick_up();
+
+    StreamReplyContext {
+      inner,
+      did_handover: false
+    }
+  }
+}
+
+// vim: set ft=rust et sw=2 ts=2 sts=2 cinoptions=2 tw=79 :
@@ -1,15 +1,207 @@
+use std::any::Any;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-use sigq::Queue as NotifyQueue;
+use crate::backlog::Node;
+use crate::err::Closed;
+use crate::handler::{AsyncHandler, Handler, ShutdownToken};
+use crate::rctx::{InnerReplyContext, ReplyContext, StreamReplyContext};
+use crate::srvq::NotifyQueue;
 
-use crate::rctx::{InnerReplyContext, ReplyContext};
+#[cfg(feature = "futures")]
+use crate::stream::ServerStream;
 
-pub(crate) struct ServerQueueNode<S, R, E> {
+pub(crate) struct ServerQueueNode<S, R, E, P> {
   /// Raw message being sent from the client to the server.
-  pub(crate) msg: S,
+  ///
+  /// This is only `None` once the server has taken the message.
+  pub(crate) msg: Option<S>,
 
   /// Keep track of data needed to share reply data.
-  pub(crate) reply: InnerReplyContext<R, E>
+  ///
+  /// This is `None` for one-way notifications.
+  pub(crate) reply: Option<InnerReplyContext<R, E, P>>,
+
+  /// Set if the client expects the reply to be streamed.
+  pub(crate) stream: bool,
+
+  /// Priority used by queues that order nodes by priority.
+  pub(crate) prio: i32,
+
+  /// Identifies the client that sent the message, and its weight, for queues
+  /// that hand out nodes fairly.
+  pub(crate) client: usize,
+  pub(crate) weight: u32,
+
+  /// Type-erase the message so it can be handed back to the client through
+  /// the reply context if the node is dropped while still in the queue.
+  ///
+  /// This is supplied by the client, where the message type is known to be
+  /// `Any`, so that the `Drop` implementation doesn't require any bounds on
+  /// `S`.
+  pub(crate) erase: fn(S) -> Box<dyn Any + Send>
+}
+
+impl<S: 'static + Send, R, E, P> ServerQueueNode<S, R, E, P> {
+  pub(crate) fn new(msg: S, reply: InnerReplyContext<R, E, P>) -> Self {
+    ServerQueueNode {
+      msg: Some(msg),
+      reply: Some(reply),
+      stream: false,
+      prio: 0,
+      client: 0,
+      weight: 1,
+      erase: |msg| Box::new(msg)
+    }
+  }
+
+  /// Create a node for a request whose reply is streamed.
+  pub(crate) fn stream(msg: S, reply: InnerReplyContext<R, E, P>) -> Self {
+    ServerQueueNode {
+      msg: Some(msg),
+      reply: Some(reply),
+      stream: true,
+      prio: 0,
+      client: 0,
+      weight: 1,
+      erase: |msg| Box::new(msg)
+    }
+  }
+
+  /// Create a node for a one-way notification, which has no reply context.
+  pub(crate) fn notification(msg: S) -> Self {
+    ServerQueueNode {
+      msg: Some(msg),
+      reply: None,
+      stream: false,
+      prio: 0,
+      client: 0,
+      weight: 1,
+      erase: |msg| Box::new(msg)
+    }
+  }
+}
+
+impl<S, R, E, P> ServerQueueNode<S, R, E, P> {
+  /// Set the priority of the node.
+  pub(crate) fn with_priority(mut self, prio: i32) -> Self {
+    self.prio = prio;
+    self
+  }
+
+  /// Record which client is sending the node, and its weight.
+  pub(crate) fn with_sender(mut self, client: usize, weight: u32) -> Self {
+    self.client = client;
+    self.weight = weight;
+    self
+  }
+
+  /// Returns `true` if the node's message is one that `pred` is looking for.
+  pub(crate) fn matches<F>(&self, pred: &mut F) -> bool
+  where
+    F: FnMut(&S) -> bool
+  {
+    self.msg.as_ref().is_some_and(pred)
+  }
+
+  /// Take the message back out of a node that never made it into the queue.
+  pub(crate) fn into_msg(mut self) -> S {
+    // Unwrap is okay; the message is only ever taken once.
+    self.msg.take().unwrap()
+  }
+
+  /// Take the message out of the node and turn the node's reply context, if
+  /// any, into an application reply context.
+  ///
+  /// Returns `None` if the node is a request whose client has stopped waiting
+  /// for a reply, in which case the server should skip it.
+  pub(crate) fn receive(mut self) -> Option<Incoming<S, R, E, P>> {
+    if let Some(ref reply) = self.reply {
+      if reply.is_cancelled() {
+        return None;
+      }
+    }
+
+    // Unwrap is okay; the message is only ever taken once.
+    let msg = self.msg.take().unwrap();
+
+    match self.reply.take() {
+      Some(reply) if self.stream => {
+        Some(Incoming::Stream(msg, StreamReplyContext::from(reply)))
+      }
+      Some(reply) => {
+        // Create an application reply context from the reply context in the
+        // queue.  Implicitly changes state of the reply context from Queued
+        // to Waiting
+        Some(Incoming::Request(msg, ReplyContext::from(reply)))
+      }
+      None => Some(Incoming::Notify(msg))
+    }
+  }
+}
+
+impl<S, R, E, P> Node for ServerQueueNode<S, R, E, P> {
+  fn priority(&self) -> i32 {
+    self.prio
+  }
+
+  fn client(&self) -> usize {
+    self.client
+  }
+
+  fn weight(&self) -> u32 {
+    self.weight
+  }
+}
+
+impl<S, R, E, P> Drop for ServerQueueNode<S, R, E, P> {
+  /// If the node is dropped while the message is still in it, then the
+  /// server never received it.  Hand the message back to the original caller
+  /// waiting for a reply.
+  ///
+  /// Notifications have nobody to hand the message back to, so they are
+  /// simply dropped.
+  fn drop(&mut self) {
+    if let Some(msg) = self.msg.take() {
+      if let Some(ref reply) = self.reply {
+        reply.abort((self.erase)(msg));
+      }
+    }
+  }
+}
+
+/// A message received by a [`Server`].
+pub enum Incoming<S, R, E, P = ()> {
+  /// A request, sent using [`Client::send()`](crate::Client::send()) or one
+  /// of its variants.  The server should reply to it using the reply
+  /// context.
+  Request(S, ReplyContext<R, E, P>),
+
+  /// A request whose reply is streamed, sent using
+  /// [`Client::send_stream()`](crate::Client::send_stream()).  The server
+  /// should send the reply items using the stream reply context.
+  Stream(S, StreamReplyContext<R, E, P>),
+
+  /// A one-way notification, sent using
+  /// [`Client::notify()`](crate::Client::notify()).  The client isn't waiting
+  /// for a reply.
+  Notify(S)
+}
+
+impl<S, R: 'static + Send, E, P> Incoming<S, R, E, P> {
+  /// Turn the message into a request.  Notifications are given a reply
+  /// context that is already cancelled, since no client is waiting for a
+  /// reply, so replies to them are handed back.  Streamed requests are given a
+  /// reply context whose reply is sent as the only item of the stream.
+  pub(crate) fn into_request(self) -> (S, ReplyContext<R, E, P>) {
+    match self {
+      Incoming::Request(msg, rctx) => (msg, rctx),
+      Incoming::Stream(msg, srctx) => (msg, srctx.into_single()),
+      Incoming::Notify(msg) => {
+        (msg, ReplyContext::picked_up(InnerReplyContext::cancelled()))
+      }
+    }
+  }
 }
 
 /// Representation of a server object.
@@ -17,15 +209,19 @@ pub(crate) struct ServerQueueNode<S, R, E> {
 /// Each instantiation of a [`Server`] object represents an end-point which
 /// will be used to receive messages from connected [`Client`](crate::Client)
 /// objects.
-pub struct Server<S, R, E> {
-  pub(crate) srvq: Arc<NotifyQueue<ServerQueueNode<S, R, E>>>
+///
+/// Servers can be [cloned](Server::clone()) to let several worker threads or
+/// tasks receive messages from the same clients.
+pub struct Server<S, R, E, P = ()> {
+  pub(crate) srvq: Arc<NotifyQueue<ServerQueueNode<S, R, E, P>>>
 }
 
-impl<S, R, E> Server<S, R, E>
+impl<S, R, E, P> Server<S, R, E, P>
 where
   S: 'static + Send,
   R: 'static + Send,
-  E: 'static + Send
+  E: 'static + Send,
+  P: 'static + Send
 {
   /// Block and wait, indefinitely, for an incoming message from a
   /// [`Client`](crate::Client).
@@ -33,31 +229,601 @@ where
   /// Returns the message sent by the client and a reply context.  The server
   /// must call [`ReplyContext::reply()`] on the reply context to pass a return
   /// value to the client.
-  pub fn wait(&self) -> (S, ReplyContext<R, E>) {
-    let node = self.srvq.pop();
+  ///
+  /// Messages whose clients stopped waiting for a reply while the message was
+  /// still in the queue are silently skipped.  Notifications sent using
+  /// [`Client::notify()`](crate::Client::notify()) are returned with a reply
+  /// context that is already [cancelled](ReplyContext::is_cancelled()), so
+  /// replying to them returns
+  /// [`ReplyError::Cancelled`](crate::ReplyError::Cancelled); use
+  /// [`Server::wait_incoming()`] to tell them apart from requests.
+  ///
+  /// Returns `None` once no more messages can arrive and all queued messages
+  /// have been received.  This happens when the server has been
+  /// [closed](Server::close()), or when every [`Client`](crate::Client) has
+  /// been dropped.
+  ///
+  /// # Example
+  /// ```
+  /// use std::thread;
+  /// use ump::channel;
+  ///
+  /// let (server, client) = channel::<u32, u32, ()>();
+  /// let server_thread = thread::spawn(move || {
+  ///   // Serve requests until all clients have gone away
+  ///   while let Some((n, rctx)) = server.wait() {
+  ///     rctx.reply(n + 1).unwrap();
+  ///   }
+  /// });
+  /// assert_eq!(client.send(1).unwrap(), 2);
+  /// drop(client);
+  /// server_thread.join().unwrap();
+  /// ```
+  pub fn wait(&self) -> Option<(S, ReplyContext<R, E, P>)> {
+    self.wait_incoming().map(Incoming::into_request)
+  }
 
-    // Extract the data from the node
-    let msg = node.msg;
+  /// Return the next message from a [`Client`](crate::Client), if one is
+  /// available, without blocking.
+  ///
+  /// Returns `Ok(None)` if there are no messages in the queue.  This allows
+  /// an event-loop to poll for client messages in between doing other work.
+  /// Returns `Err(Closed)` once no more messages can arrive, under the same
+  /// conditions as [`Server::wait()`] returns `None`.
+  ///
+  /// # Example
+  /// ```
+  /// use ump::{channel, Closed};
+  ///
+  /// let (server, client) = channel::<u32, u32, ()>();
+  /// assert!(matches!(server.try_wait(), Ok(None)));
+  ///
+  /// client.notify(1).unwrap();
+  /// drop(client);
+  /// let (n, _rctx) = server.try_wait().unwrap().unwrap();
+  /// assert_eq!(n, 1);
+  ///
+  /// // The client is gone, so no more messages will arrive.
+  /// assert!(matches!(server.try_wait(), Err(Closed)));
+  /// ```
+  #[allow(clippy::type_complexity)]
+  pub fn try_wait(
+    &self
+  ) -> Result<Option<(S, ReplyContext<R, E, P>)>, Closed> {
+    let incoming = self.try_wait_incoming()?;
+    Ok(incoming.map(Incoming::into_request))
+  }
 
-    // Create an application reply context from the reply context in the queue
-    // Implicitly changes state of the reply context from Queued to Waiting
-    let rctx = ReplyContext::from(node.reply);
+  /// Same as [`Server::wait()`], but also give up and return `Ok(None)` if
+  /// no message has arrived within `timeout`.
+  ///
+  /// Returns `Err(Closed)` once no more messages can arrive.
+  #[allow(clippy::type_complexity)]
+  pub fn wait_timeout(
+    &self,
+    timeout: Duration
+  ) -> Result<Option<(S, ReplyContext<R, E, P>)>, Closed> {
+    let incoming = self.wait_incoming_timeout(timeout)?;
+    Ok(incoming.map(Incoming::into_request))
+  }
 
-    (msg, rctx)
+  /// Same as [`Server::wait()`], but also give up and return `Ok(None)` if
+  /// no message has arrived by `deadline`.
+  ///
+  /// Returns `Err(Closed)` once no more messages can arrive.
+  #[allow(clippy::type_complexity)]
+  pub fn wait_deadline(
+    &self,
+    deadline: Instant
+  ) -> Result<Option<(S, ReplyContext<R, E, P>)>, Closed> {
+    let incoming = self.wait_incoming_deadline(deadline)?;
+    Ok(incoming.map(Incoming::into_request))
   }
 
   /// Same as [`Server::wait()`], but for use in an `async` context.
-  pub async fn async_wait(&self) -> (S, ReplyContext<R, E>) {
-    let node = self.srvq.apop().await;
+  pub async fn async_wait(&self) -> Option<(S, ReplyContext<R, E, P>)> {
+    self.async_wait_incoming().await.map(Incoming::into_request)
+  }
+
+  /// Same as [`Server::wait()`], but distinguishes requests from one-way
+  /// notifications sent using [`Client::notify()`](crate::Client::notify()).
+  pub fn wait_incoming(&self) -> Option<Incoming<S, R, E, P>> {
+    loop {
+      if let Some(incoming) = self.srvq.pop()?.receive() {
+        break Some(incoming);
+      }
+    }
+  }
+
+  /// Same as [`Server::try_wait()`], but distinguishes requests from one-way
+  /// notifications.
+  pub fn try_wait_incoming(
+    &self
+  ) -> Result<Option<Incoming<S, R, E, P>>, Closed> {
+    loop {
+      match self.srvq.try_pop() {
+        Some(node) => {
+          if let Some(incoming) = node.receive() {
+            break Ok(Some(incoming));
+          }
+        }
+        None => break self.nothing_popped()
+      }
+    }
+  }
+
+  /// Same as [`Server::wait_timeout()`], but distinguishes requests from
+  /// one-way notifications.
+  pub fn wait_incoming_timeout(
+    &self,
+    timeout: Duration
+  ) -> Result<Option<Incoming<S, R, E, P>>, Closed> {
+    match Instant::now().checked_add(timeout) {
+      Some(deadline) => self.wait_incoming_deadline(deadline),
+      None => self.wait_incoming().map(Some).ok_or(Closed)
+    }
+  }
+
+  /// Same as [`Server::wait_deadline()`], but distinguishes requests from
+  /// one-way notifications.
+  pub fn wait_incoming_deadline(
+    &self,
+    deadline: Instant
+  ) -> Result<Option<Incoming<S, R, E, P>>, Closed> {
+    loop {
+      match self.srvq.pop_deadline(deadline) {
+        Some(node) => {
+          if let Some(incoming) = node.receive() {
+            break Ok(Some(incoming));
+          }
+        }
+        None => break self.nothing_popped()
+      }
+    }
+  }
+
+  /// Same as [`Server::wait_incoming()`], but for use in an `async` context.
+  pub async fn async_wait_incoming(&self) -> Option<Incoming<S, R, E, P>> {
+    loop {
+      if let Some(incoming) = self.srvq.apop().await?.receive() {
+        break Some(incoming);
+      }
+    }
+  }
+
+  /// Block and wait for a message for which `pred` returns `true`, leaving
+  /// any other messages in the queue in the order they were sent.
+  ///
+  /// Returns the first matching message in the queue, if there is one, or
+  /// waits for a matching message to arrive.  This allows a server to, for
+  /// instance, wait for a `Commit` message after having received a `Begin`,
+  /// and then carry on receiving the messages that were sent in between.
+  ///
+  /// The predicate is called while the server's queue is locked, so it
+  /// should be quick, and must not call into the server or any of its
+  /// clients.
+  ///
+  /// Returns `None` once there's no matching message in the queue and no more
+  /// messages can arrive; the server has been [closed](Server::close()), or
+  /// every [`Client`](crate::Client) has been dropped.
+  ///
+  /// # Example
+  /// ```
+  /// use ump::channel;
+  ///
+  /// enum Msg {
+  ///   Begin,
+  ///   Put(u32),
+  ///   Commit
+  /// }
+  ///
+  /// let (server, client) = channel::<Msg, (), ()>();
+  /// client.notify(Msg::Begin).unwrap();
+  /// client.notify(Msg::Put(1)).unwrap();
+  /// client.notify(Msg::Put(2)).unwrap();
+  /// client.notify(Msg::Commit).unwrap();
+  ///
+  /// let (msg, _) = server.wait().unwrap();
+  /// assert!(matches!(msg, Msg::Begin));
+  ///
+  /// // Skip ahead to the commit ..
+  /// let is_commit = |msg: &Msg| matches!(msg, Msg::Commit);
+  /// let (msg, _) = server.wait_matching(is_commit).unwrap();
+  /// assert!(matches!(msg, Msg::Commit));
+  ///
+  /// // .. and then receive the messages in between
+  /// let (msg, _) = server.wait().unwrap();
+  /// assert!(matches!(msg, Msg::Put(1)));
+  /// let (msg, _) = server.wait().unwrap();
+  /// assert!(matches!(msg, Msg::Put(2)));
+  /// ```
+  pub fn wait_matching<F>(
+    &self,
+    mut pred: F
+  ) -> Option<(S, ReplyContext<R, E, P>)>
+  where
+    F: FnMut(&S) -> bool
+  {
+    loop {
+      let node = self.srvq.pop_matching(|node| node.matches(&mut pred))?;
+      if let Some(incoming) = node.receive() {
+        break Some(incoming.into_request());
+      }
+    }
+  }
+
+  /// Same as [`Server::wait_matching()`], but return `Ok(None)` rather than
+  /// block if there's no matching message in the queue.
+  ///
+  /// Returns `Err(Closed)` once there's no matching message in the queue and
+  /// no more messages can arrive.
+  #[allow(clippy::type_complexity)]
+  pub fn try_wait_matching<F>(
+    &self,
+    mut pred: F
+  ) -> Result<Option<(S, ReplyContext<R, E, P>)>, Closed>
+  where
+    F: FnMut(&S) -> bool
+  {
+    loop {
+      match self.srvq.try_pop_matching(|node| node.matches(&mut pred)) {
+        Some(node) => {
+          if let Some(incoming) = node.receive() {
+            break Ok(Some(incoming.into_request()));
+          }
+        }
+        None => {
+          // Once no more messages can arrive the queue only shrinks, so a
+          // matching message will never turn up.
+          if self.srvq.is_finished() {
+            break Err(Closed);
+          }
+          break Ok(None);
+        }
+      }
+    }
+  }
+
+  /// Same as [`Server::wait_matching()`], but for use in an `async` context.
+  pub async fn async_wait_matching<F>(
+    &self,
+    mut pred: F
+  ) -> Option<(S, ReplyContext<R, E, P>)>
+  where
+    F: FnMut(&S) -> bool
+  {
+    loop {
+      let node = self
+        .srvq
+        .apop_matching(|node: &ServerQueueNode<S, R, E, P>| {
+          node.matches(&mut pred)
+        })
+        .await?;
+      if let Some(incoming) = node.receive() {
+        break Some(incoming.into_request());
+      }
+    }
+  }
+
+  /// Block and wait for at least one message from a client, and then return
+  /// it along with any other messages already waiting in the queue, up to
+  /// `max` messages in all.
+  ///
+  /// The messages are returned in the order [`Server::wait()`] would have
+  /// returned them, each with its own reply context.  This lets a server
+  /// amortise expensive work, such as committing to a database, over several
+  /// requests.  Messages sent using
+  /// [`Client::send_many()`](crate::Client::send_many()) are queued together,
+  /// so they're received in the same batch as long as `max` allows for it.
+  ///
+  /// Returns `None` under the same conditions as [`Server::wait()`]; a
+  /// returned batch is never empty.
+  ///
+  /// # Example
+  /// ```
+  /// use ump::channel;
+  ///
+  /// let (server, client) = channel::<u32, u32, ()>();
+  /// let pending: Vec<_> = (1..=5).map(|n| client.post(n).unwrap()).collect();
+  /// drop(client);
+  ///
+  /// // Reply to each request with the sum of its batch
+  /// while let Some(batch) = server.wait_batch(3) {
+  ///   let sum: u32 = batch.iter().map(|(n, _)| n).sum();
+  ///   for (_, rctx) in batch {
+  ///     rctx.reply(sum).unwrap();
+  ///   }
+  /// }
+  ///
+  /// let replies: Vec<u32> =
+  ///   pending.into_iter().map(|wrply| wrply.wait().unwrap()).collect();
+  /// assert_eq!(replies, vec![6, 6, 6, 9, 9]);
+  /// ```
+  ///
+  /// # Panics
+  /// Panics if `max` is zero.
+  #[allow(clippy::type_complexity)]
+  pub fn wait_batch(
+    &self,
+    max: usize
+  ) -> Option<Vec<(S, ReplyContext<R, E, P>)>> {
+    assert!(max > 0, "Batch size must be non-zero");
+    let mut batch = vec![self.wait()?];
+    self.fill_batch(&mut batch, max);
+    Some(batch)
+  }
+
+  /// Same as [`Server::wait_batch()`], but if the batch isn't full once the
+  /// first message has arrived, keep waiting for more messages for up to
+  /// `linger`.
+  ///
+  /// The batch is returned as soon as it's full, when `linger` has elapsed,
+  /// or when no more messages can arrive.
+  ///
+  /// # Panics
+  /// Panics if `max` is zero.
+  #[allow(clippy::type_complexity)]
+  pub fn wait_batch_linger(
+    &self,
+    max: usize,
+    linger: Duration
+  ) -> Option<Vec<(S, ReplyContext<R, E, P>)>> {
+    let mut batch = self.wait_batch(max)?;
+    let deadline = Instant::now().checked_add(linger);
+    while batch.len() < max {
+      let incoming = match deadline {
+        Some(deadline) => self.wait_incoming_deadline(deadline),
+        None => self.wait_incoming().map(Some).ok_or(Closed)
+      };
+      match incoming {
+        Ok(Some(incoming)) => batch.push(incoming.into_request()),
+        Ok(None) | Err(Closed) => break
+      }
+      self.fill_batch(&mut batch, max);
+    }
+    Some(batch)
+  }
+
+  /// Same as [`Server::wait_batch()`], but return `Ok(None)` rather than
+  /// block if there are no messages in the queue.
+  ///
+  /// Returns `Err(Closed)` once no more messages can arrive.
+  ///
+  /// # Panics
+  /// Panics if `max` is zero.
+  #[allow(clippy::type_complexity)]
+  pub fn try_wait_batch(
+    &self,
+    max: usize
+  ) -> Result<Option<Vec<(S, ReplyContext<R, E, P>)>>, Closed> {
+    assert!(max > 0, "Batch size must be non-zero");
+    let mut batch = match self.try_wait()? {
+      Some(first) => vec![first],
+      None => return Ok(None)
+    };
+    self.fill_batch(&mut batch, max);
+    Ok(Some(batch))
+  }
+
+  /// Same as [`Server::wait_batch()`], but for use in an `async` context.
+  ///
+  /// # Panics
+  /// Panics if `max` is zero.
+  #[allow(clippy::type_complexity)]
+  pub async fn async_wait_batch(
+    &self,
+    max: usize
+  ) -> Option<Vec<(S, ReplyContext<R, E, P>)>> {
+    assert!(max > 0, "Batch size must be non-zero");
+    let mut batch = vec![self.async_wait().await?];
+    self.fill_batch(&mut batch, max);
+    Some(batch)
+  }
+
+  /// Same as [`Server::wait_batch_linger()`], but for use in an `async`
+  /// context.
+  ///
+  /// # Panics
+  /// Panics if `max` is zero.
+  #[allow(clippy::type_complexity)]
+  pub async fn async_wait_batch_linger(
+    &self,
+    max: usize,
+    linger: Duration
+  ) -> Option<Vec<(S, ReplyContext<R, E, P>)>> {
+    let mut batch = self.async_wait_batch(max).await?;
+    let deadline = Instant::now().checked_add(linger);
+    while batch.len() < max {
+      let node = match deadline {
+        Some(deadline) => self.srvq.apop_deadline(deadline).await,
+        None => self.srvq.apop().await
+      };
+      match node {
+        Some(node) => {
+          if let Some(incoming) = node.receive() {
+            batch.push(incoming.into_request());
+          }
+        }
+        None => break
+      }
+      self.fill_batch(&mut batch, max);
+    }
+    Some(batch)
+  }
+
+  /// Nothing was popped off the queue; tell whether that's because no more
+  /// messages will arrive, or just because none has arrived yet.
+  fn nothing_popped<T>(&self) -> Result<Option<T>, Closed> {
+    if self.srvq.is_drained() {
+      Err(Closed)
+    } else {
+      Ok(None)
+    }
+  }
+
+  /// Add the messages already waiting in the queue to `batch`, until it holds
+  /// `max` messages.
+  fn fill_batch(
+    &self,
+    batch: &mut Vec<(S, ReplyContext<R, E, P>)>,
+    max: usize
+  ) {
+    while batch.len() < max {
+      let nodes = self.srvq.try_pop_many(max - batch.len());
+      if nodes.is_empty() {
+        break;
+      }
+      batch.extend(
+        nodes
+          .into_iter()
+          .filter_map(ServerQueueNode::receive)
+          .map(Incoming::into_request)
+      );
+    }
+  }
+
+  /// Receive messages and pass them to `handler`, one at a time, until no
+  /// more messages can arrive.
+  ///
+  /// Each message is replied to with the handler's result:  `Ok` is sent as
+  /// the reply, and `Err` is sent as an [application
+  /// error](crate::Error::App). Replies to clients that have stopped waiting,
+  /// and to notifications, are discarded.
+  ///
+  /// Returns once [`Server::wait()`] would return `None`; i.e. once every
+  /// [`Client`](crate::Client) has been dropped, or the server has been shut
+  /// down using a [`ShutdownToken`] (or [closed](Server::close())), and the
+  /// messages already in the queue have been handled.
+  ///
+  /// # Example
+  /// ```
+  /// use std::thread;
+  /// use ump::channel;
+  ///
+  /// let (server, client) = channel::<u32, u32, String>();
+  /// let shutdown = server.shutdown_token();
+  /// let double = |n: u32| n.checked_mul(2).ok_or(String::from("Overflow"));
+  /// let server_thread = thread::spawn(move || server.serve(double));
+  ///
+  /// assert_eq!(client.send(21).unwrap(), 42);
+  /// assert!(client.send(u32::MAX).is_err());
+  ///
+  /// // Stop the server, even though there's a client left.
+  /// shutdown.shutdown();
+  /// server_thread.join().unwrap();
+  /// ```
+  pub fn serve<H>(&self, mut handler: H)
+  where
+    H: Handler<S, R, E>
+  {
+    while let Some((msg, rctx)) = self.wait() {
+      respond(rctx, handler.handle(msg));
+    }
+  }
+
+  /// Same as [`Server::serve()`], but for use in an `async` context.
+  pub async fn async_serve<H>(&self, mut handler: H)
+  where
+    H: AsyncHandler<S, R, E>
+  {
+    while let Some((msg, rctx)) = self.async_wait().await {
+      respond(rctx, handler.handle(msg).await);
+    }
+  }
+
+  /// Create a token which can be used to stop the server's
+  /// [run loop](Server::serve()) from another thread or task.
+  pub fn shutdown_token(&self) -> ShutdownToken<S, R, E, P> {
+    ShutdownToken {
+      srvq: Arc::downgrade(&self.srvq)
+    }
+  }
+
+  /// Turn the server into a [`Stream`](futures_core::Stream) of incoming
+  /// messages and their reply contexts.
+  ///
+  /// The stream ends when [`Server::wait()`] would return `None`; once there
+  /// are no clients left, or the server has been [closed](Server::close()),
+  /// and all queued messages have been received.
+  ///
+  /// # Example
+  /// ```
+  /// use futures::StreamExt;
+  /// use ump::channel;
+  ///
+  /// let (server, client) = channel::<u32, u32, ()>();
+  /// let pending: Vec<_> = (1..=3).map(|n| client.post(n).unwrap()).collect();
+  /// drop(client);
+  ///
+  /// let tokrt = tokio::runtime::Runtime::new().unwrap();
+  /// tokrt.block_on(async {
+  ///   server
+  ///     .into_stream()
+  ///     .for_each_concurrent(None, |(n, rctx)| async move {
+  ///       rctx.reply(n * 2).unwrap();
+  ///     })
+  ///     .await;
+  /// });
+  ///
+  /// let replies: Vec<u32> =
+  ///   pending.into_iter().map(|w| w.wait().unwrap()).collect();
+  /// assert_eq!(replies, vec![2, 4, 6]);
+  /// ```
+  #[cfg(feature = "futures")]
+  pub fn into_stream(self) -> ServerStream<S, R, E, P> {
+    ServerStream::new(self)
+  }
 
-    // Extract the data from the node
-    let msg = node.msg;
+  /// Stop accepting new messages, without dropping the messages that have
+  /// already been queued.
+  ///
+  /// Once closed, clients trying to send messages will get
+  /// [`Error::ServerClosed`](crate::Error::ServerClosed) back.  The server,
+  /// and any of its clones, keep receiving the messages that were queued
+  /// before the server was closed, after which [`Server::wait()`] returns
+  /// `None`.  This allows a server to shut down without losing any requests it
+  /// has already accepted.
+  ///
+  /// # Example
+  /// ```
+  /// use std::thread;
+  /// use ump::{channel, Error};
+  ///
+  /// let (server, client) = channel::<u32, u32, ()>();
+  /// let first = client.post(1).unwrap();
+  /// server.close();
+  ///
+  /// // New requests are rejected ..
+  /// match client.send(2) {
+  ///   Err(Error::ServerClosed(2)) => {}
+  ///   _ => panic!("Unexpected result")
+  /// }
+  ///
+  /// // .. but queued requests are still delivered.
+  /// let server_thread = thread::spawn(move || {
+  ///   while let Some((n, rctx)) = server.wait() {
+  ///     rctx.reply(n * 2).unwrap();
+  ///   }
+  /// });
+  /// assert_eq!(first.wait().unwrap(), 2);
+  /// server_thread.join().unwrap();
+  /// ```
+  pub fn close(&self) {
+    self.srvq.close();
+  }
 
-    // Create an application reply context from the reply context in the queue
-    // Implicitly changes state of the reply context from Queued to Waiting
-    let rctx = ReplyContext::from(node.reply);
+  /// Returns `true` if the server has been [closed](Server::close()).
+  pub fn is_closed(&self) -> bool {
+    self.srvq.is_closed()
+  }
 
-    (msg, rctx)
+  /// Returns `true` if there are any [`Client`](crate::Client)s left that
+  /// can send messages to the server.
+  ///
+  /// Once this returns `false` it will never return `true` again, since new
+  /// clients can only be created by cloning existing ones.
+  pub fn has_clients(&self) -> bool {
+    self.srvq.has_clients()
   }
 
   /// Returns a boolean indicating whether the queue is/was empty.  This isn't
@@ -68,4 +834,44 @@ where
   }
 }
 
+/// Send a handler's result back to the client.
+fn respond<R: 'static + Send, E, P>(
+  rctx: ReplyContext<R, E, P>,
+  res: Result<R, E>
+) {
+  // The only reason these can fail is that the client has stopped waiting,
+  // in which case there's nobody to tell.
+  match res {
+    Ok(reply) => {
+      let _ = rctx.reply(reply);
+    }
+    Err(err) => {
+      let _ = rctx.fail(err);
+    }
+  }
+}
+
+impl<S, R, E, P> Clone for Server<S, R, E, P> {
+  /// Clone a server, creating another end-point which receives messages from
+  /// the same queue as the original.
+  ///
+  /// Each message is delivered to exactly one of the servers.  Servers waiting
+  /// for messages are handed them in the order they started waiting, so the
+  /// load is spread across all idle servers.
+  fn clone(&self) -> Self {
+    self.srvq.add_server();
+    Server {
+      srvq: Arc::clone(&self.srvq)
+    }
+  }
+}
+
+impl<S, R, E, P> Drop for Server<S, R, E, P> {
+  /// Once the last server has been dropped, release any clients waiting for
+  /// space in the queue, and abort all requests that are still queued.
+  fn drop(&mut self) {
+    self.srvq.remove_server();
+  }
+}
+
 // vim: set ft=rust et sw=2 ts=2 sts=2 cinoptions=2 tw=79 :
@@ -0,0 +1,796 @@
+//! Queue used to pass nodes from clients to a server, crossing sync/async
+//! boundaries.
+//!
+//! The queue can optionally be bounded, in which case pushing a node onto a
+//! full queue will block (or wait, in `async` contexts) until the server has
+//! popped a node off it.
+//!
+//! Several servers may pop nodes off the same queue.  Servers waiting for
+//! nodes, whether in sync or `async` contexts, are woken one at a time in the
+//! order they started waiting.
+//!
+//! Servers may also wait for a node matching a predicate.  These are kept in
+//! a separate list and are all woken each time a node is pushed, since only
+//! they can tell whether the new node is one they're interested in.
+
+use std::collections::VecDeque;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Condvar, Mutex, MutexGuard, PoisonError};
+use std::task::{Context, Poll, Waker};
+use std::thread;
+use std::time::Instant;
+
+use crate::backlog::{Backlog, Node};
+use crate::timer;
+use crate::waker::{self, Waiters};
+
+/// Reasons a node could not be pushed onto the queue.
+pub(crate) enum PushError<I> {
+  /// The queue is bounded and is full.  For calls that wait for space to
+  /// become available this means that the deadline expired.
+  Full(I),
+
+  /// The queue has been closed.
+  Closed(I),
+
+  /// The server has been dropped.
+  Disconnected(I)
+}
+
+impl<I> PushError<I> {
+  /// Replace the node(s) that could not be pushed, keeping the reason.
+  pub(crate) fn map<T>(self, f: impl FnOnce(I) -> T) -> PushError<T> {
+    match self {
+      PushError::Full(item) => PushError::Full(f(item)),
+      PushError::Closed(item) => PushError::Closed(f(item)),
+      PushError::Disconnected(item) => PushError::Disconnected(f(item))
+    }
+  }
+}
+
+fn wake(waker: Option<Waker>) {
+  if let Some(waker) = waker {
+    waker.wake();
+  }
+}
+
+struct Inner<I> {
+  q: Backlog<I>,
+
+  /// Maximum number of nodes in the queue, if bounded.
+  cap: Option<usize>,
+
+  /// Number of servers popping nodes off the queue.
+  servers: usize,
+
+  /// Number of clients able to push nodes onto the queue.
+  clients: usize,
+
+  /// Identifier to give the next client.
+  next_client: usize,
+
+  /// Set once the queue has been closed.  No new nodes are accepted, but
+  /// the ones already in the queue can still be popped.
+  closed: bool,
+
+  /// Set once the last server has been dropped.
+  disconnected: bool,
+
+  /// Tasks waiting for a node to be pushed.
+  poppers: Waiters,
+
+  /// Tasks waiting for a node matching a predicate to be pushed.
+  matchers: Waiters,
+
+  /// Tasks waiting for space to become available.
+  pushers: Waiters
+}
+
+impl<I> Inner<I> {
+  /// Returns the reason new nodes can't be pushed onto the queue, if any.
+  fn reject<T>(&self, item: T) -> Result<T, PushError<T>> {
+    if self.disconnected {
+      Err(PushError::Disconnected(item))
+    } else if self.closed {
+      Err(PushError::Closed(item))
+    } else {
+      Ok(item)
+    }
+  }
+
+  /// Returns `true` if no more nodes will be popped off the queue; the queue
+  /// is empty, and either it has been closed or there are no clients left to
+  /// push new nodes onto it.
+  fn is_drained(&self) -> bool {
+    self.q.is_empty() && self.is_finished()
+  }
+
+  /// Returns `true` if no new nodes will be pushed onto the queue.
+  fn is_finished(&self) -> bool {
+    self.closed || self.clients == 0
+  }
+
+  fn is_full(&self) -> bool {
+    match self.cap {
+      Some(cap) => self.q.len() >= cap,
+      None => false
+    }
+  }
+
+  /// A popper that was waiting is giving up.  If it was woken, but didn't
+  /// get a chance to pop a node, then the wakeup must be passed on to another
+  /// waiting popper.  Returns the waker to wake once the lock has been
+  /// released.
+  fn abandon_pop(&mut self, id: Option<usize>) -> Option<Waker> {
+    match id {
+      Some(id) if !self.poppers.remove(id) && !self.q.is_empty() => {
+        self.poppers.take_one()
+      }
+      _ => None
+    }
+  }
+}
+
+pub(crate) struct NotifyQueue<I> {
+  inner: Mutex<Inner<I>>,
+
+  /// Signalled when a node has been popped from a bounded queue.
+  space: Condvar
+}
+
+impl<I> NotifyQueue<I> {
+  /// Create a new queue, with a single server, which hands out nodes in the
+  /// order determined by `q`.  If `cap` is `Some`, the queue will hold at
+  /// most that many nodes.
+  pub(crate) fn new(q: Backlog<I>, cap: Option<usize>) -> Self {
+    NotifyQueue {
+      inner: Mutex::new(Inner {
+        q,
+        cap,
+        servers: 1,
+        clients: 1,
+        next_client: 1,
+        closed: false,
+        disconnected: false,
+        poppers: Waiters::new(),
+        matchers: Waiters::new(),
+        pushers: Waiters::new()
+      }),
+      space: Condvar::new()
+    }
+  }
+
+  /// Lock the queue.
+  ///
+  /// The only application code run while the lock is held are the predicates
+  /// passed to [`NotifyQueue::pop_matching()`] and its variants, which are
+  /// run before the queue is modified.  Ignore lock poisoning rather than
+  /// propagating panics between threads.
+  fn lock(&self) -> MutexGuard<'_, Inner<I>> {
+    self.inner.lock().unwrap_or_else(PoisonError::into_inner)
+  }
+
+  /// Returns a boolean indicating whether the queue was empty or not.
+  pub(crate) fn was_empty(&self) -> bool {
+    let inner = self.lock();
+    inner.q.is_empty()
+  }
+
+  /// Push a node on to the queue and wake up one queue reader, if any.  If the
+  /// queue is full, block and wait for space to become available.  If a
+  /// `deadline` is given, give up waiting for space once it has expired.
+  pub(crate) fn push(
+    &self,
+    mut item: I,
+    deadline: Option<Instant>
+  ) -> Result<(), PushError<I>>
+  where
+    I: Node
+  {
+    let mut inner = self.lock();
+    loop {
+      item = inner.reject(item)?;
+      if !inner.is_full() {
+        break;
+      }
+      inner = match deadline {
+        Some(deadline) => {
+          let now = Instant::now();
+          if now >= deadline {
+            return Err(PushError::Full(item));
+          }
+          self
+            .space
+            .wait_timeout(inner, deadline - now)
+            .unwrap_or_else(PoisonError::into_inner)
+            .0
+        }
+        None => self
+          .space
+          .wait(inner)
+          .unwrap_or_else(PoisonError::into_inner)
+      };
+    }
+    self.push_locked(inner, item);
+    Ok(())
+  }
+
+  /// Push a node on to the queue, unless the queue is full.
+  pub(crate) fn try_push(&self, item: I) -> Result<(), PushError<I>>
+  where
+    I: Node
+  {
+    let inner = self.lock();
+    let item = inner.reject(item)?;
+    if inner.is_full() {
+      return Err(PushError::Full(item));
+    }
+    self.push_locked(inner, item);
+    Ok(())
+  }
+
+  /// Push as many of `items` onto the queue as there is space for, in order
+  /// and under a single lock, and wake up as many queue readers as there
+  /// were nodes pushed.  Returns the nodes that didn't fit.
+  ///
+  /// If no nodes can be pushed because the queue has been closed, or the
+  /// server has been dropped, all of them are returned in the error.
+  pub(crate) fn push_some(
+    &self,
+    mut items: VecDeque<I>
+  ) -> Result<VecDeque<I>, PushError<VecDeque<I>>>
+  where
+    I: Node
+  {
+    let mut inner = self.lock();
+    items = inner.reject(items)?;
+    let mut n = 0;
+    while !inner.is_full() {
+      match items.pop_front() {
+        Some(item) => inner.q.push(item),
+        None => break
+      }
+      n += 1;
+    }
+    if n == 0 {
+      return Ok(items);
+    }
+    let wakers = inner.poppers.take(n);
+    let matchers = inner.matchers.take_all();
+    drop(inner);
+
+    for waker in wakers.into_iter().chain(matchers) {
+      waker.wake();
+    }
+    Ok(items)
+  }
+
+  /// Same as [`NotifyQueue::push()`], but returns a `Future` for use in an
+  /// `async` context.
+  pub(crate) fn apush(
+    &self,
+    item: I,
+    deadline: Option<Instant>
+  ) -> PushFuture<'_, I> {
+    PushFuture {
+      q: self,
+      item: Some(item),
+      deadline,
+      timer: None,
+      id: None
+    }
+  }
+
+  fn push_locked(&self, mut inner: MutexGuard<'_, Inner<I>>, item: I)
+  where
+    I: Node
+  {
+    inner.q.push(item);
+    let waker = inner.poppers.take_one();
+    let matchers = inner.matchers.take_all();
+    drop(inner);
+
+    wake(waker);
+    for waker in matchers {
+      waker.wake();
+    }
+  }
+
+  /// Pull the oldest node off the queue and return it.  If no nodes are
+  /// available on the queue, then block and wait for one to become available.
+  ///
+  /// Returns `None` once the queue is empty and it has been closed, or there
+  /// are no clients left.
+  pub(crate) fn pop(&self) -> Option<I> {
+    self.pop_until(None)
+  }
+
+  /// Same as [`NotifyQueue::pop()`], but also give up and return `None` if no
+  /// node has become available by `deadline`.
+  pub(crate) fn pop_deadline(&self, deadline: Instant) -> Option<I> {
+    self.pop_until(Some(deadline))
+  }
+
+  fn pop_until(&self, deadline: Option<Instant>) -> Option<I> {
+    let waker = waker::current_thread();
+    let mut id = None;
+    let mut inner = self.lock();
+    loop {
+      if let Some(node) = inner.q.pop() {
+        if let Some(id) = id {
+          inner.poppers.remove(id);
+        }
+        self.popped(inner);
+        break Some(node);
+      }
+      if inner.is_drained() {
+        if let Some(id) = id {
+          inner.poppers.remove(id);
+        }
+        break None;
+      }
+      let timeout = match deadline {
+        Some(deadline) => {
+          let now = Instant::now();
+          if now >= deadline {
+            let waker = inner.abandon_pop(id);
+            drop(inner);
+            wake(waker);
+            break None;
+          }
+          Some(deadline - now)
+        }
+        None => None
+      };
+
+      // Spurious wakeups are harmless; the queue is rechecked regardless.
+      inner.poppers.register(&mut id, &waker);
+      drop(inner);
+      match timeout {
+        Some(timeout) => thread::park_timeout(timeout),
+        None => thread::park()
+      }
+      inner = self.lock();
+    }
+  }
+
+  /// Pull the oldest node off the queue and return it.  If no nodes are
+  /// available on the queue, then return `None`.
+  pub(crate) fn try_pop(&self) -> Option<I> {
+    let mut inner = self.lock();
+    let node = inner.q.pop()?;
+    self.popped(inner);
+    Some(node)
+  }
+
+  /// Pull up to `max` nodes off the queue, in the order they would have been
+  /// popped one at a time, without waiting for any to become available.
+  pub(crate) fn try_pop_many(&self, max: usize) -> Vec<I> {
+    let mut inner = self.lock();
+    let mut nodes = Vec::new();
+    while nodes.len() < max {
+      match inner.q.pop() {
+        Some(node) => nodes.push(node),
+        None => break
+      }
+    }
+    if !nodes.is_empty() {
+      self.popped_many(inner, nodes.len());
+    }
+    nodes
+  }
+
+  /// Same as [`NotifyQueue::pop()`], but returns a `Future` for use in an
+  /// `async` context.
+  pub(crate) fn apop(&self) -> PopFuture<'_, I> {
+    PopFuture {
+      q: self,
+      deadline: None,
+      timer: None,
+      id: None
+    }
+  }
+
+  /// Same as [`NotifyQueue::pop_deadline()`], but returns a `Future` for use
+  /// in an `async` context.
+  pub(crate) fn apop_deadline(&self, deadline: Instant) -> PopFuture<'_, I> {
+    PopFuture {
+      q: self,
+      deadline: Some(deadline),
+      timer: None,
+      id: None
+    }
+  }
+
+  /// Pop a node off the queue if one is available, otherwise register the
+  /// task's waker with the list of waiting poppers.  `id` identifies the task
+  /// in that list.
+  ///
+  /// Returns `Ready(None)` once no more nodes will arrive; i.e. the queue is
+  /// empty and it has been closed or there are no clients left.
+  pub(crate) fn poll_pop(
+    &self,
+    id: &mut Option<usize>,
+    ctx: &mut Context<'_>
+  ) -> Poll<Option<I>> {
+    let mut inner = self.lock();
+    match inner.q.pop() {
+      Some(node) => {
+        if let Some(id) = id.take() {
+          inner.poppers.remove(id);
+        }
+        self.popped(inner);
+        Poll::Ready(Some(node))
+      }
+      None if inner.is_drained() => {
+        if let Some(id) = id.take() {
+          inner.poppers.remove(id);
+        }
+        Poll::Ready(None)
+      }
+      None => {
+        inner.poppers.register(id, ctx.waker());
+        Poll::Pending
+      }
+    }
+  }
+
+  /// A task waiting in [`NotifyQueue::poll_pop()`] is giving up.  If it was
+  /// woken, but is dropped before it got a chance to pop a node, then pass
+  /// the wakeup on to another waiting task.
+  pub(crate) fn cancel_pop(&self, id: &mut Option<usize>) {
+    if id.is_some() {
+      let mut inner = self.lock();
+      let waker = inner.abandon_pop(id.take());
+      drop(inner);
+      wake(waker);
+    }
+  }
+
+  /// Pull the first node for which `pred` returns `true` off the queue,
+  /// leaving the other nodes in the queue.  If there is no such node, block
+  /// and wait until one is pushed.
+  ///
+  /// Returns `None` once there's no matching node in the queue and no more
+  /// nodes will arrive, because the queue has been closed or there are no
+  /// clients left.
+  pub(crate) fn pop_matching<F>(&self, mut pred: F) -> Option<I>
+  where
+    F: FnMut(&I) -> bool
+  {
+    let waker = waker::current_thread();
+    let mut id = None;
+    let mut inner = self.lock();
+    loop {
+      let node = inner.q.pop_matching(&mut pred);
+      if node.is_some() || inner.is_finished() {
+        if let Some(id) = id {
+          inner.matchers.remove(id);
+        }
+        if node.is_some() {
+          self.popped(inner);
+        }
+        break node;
+      }
+
+      // Spurious wakeups are harmless; the queue is rechecked regardless.
+      inner.matchers.register(&mut id, &waker);
+      drop(inner);
+      thread::park();
+      inner = self.lock();
+    }
+  }
+
+  /// Pull the first node for which `pred` returns `true` off the queue.  If
+  /// there is no such node, then return `None`.
+  pub(crate) fn try_pop_matching<F>(&self, pred: F) -> Option<I>
+  where
+    F: FnMut(&I) -> bool
+  {
+    let mut inner = self.lock();
+    let node = inner.q.pop_matching(pred)?;
+    self.popped(inner);
+    Some(node)
+  }
+
+  /// Same as [`NotifyQueue::pop_matching()`], but returns a `Future` for use
+  /// in an `async` context.
+  pub(crate) fn apop_matching<F>(&self, pred: F) -> PopMatchingFuture<'_, I, F>
+  where
+    F: FnMut(&I) -> bool
+  {
+    PopMatchingFuture {
+      q: self,
+      pred,
+      id: None
+    }
+  }
+
+  /// A node has been popped; if the queue is bounded, let a blocked pusher
+  /// know that there's space available.
+  fn popped(&self, mut inner: MutexGuard<'_, Inner<I>>) {
+    if inner.cap.is_some() {
+      let waker = inner.pushers.take_one();
+      drop(inner);
+
+      self.space.notify_one();
+      wake(waker);
+    }
+  }
+
+  /// Same as [`NotifyQueue::popped()`], but `n` nodes have been popped, so
+  /// let up to `n` blocked pushers know.
+  fn popped_many(&self, mut inner: MutexGuard<'_, Inner<I>>, n: usize) {
+    if inner.cap.is_some() {
+      let wakers = inner.pushers.take(n);
+      drop(inner);
+
+      for _ in 0..n {
+        self.space.notify_one();
+      }
+      for waker in wakers {
+        waker.wake();
+      }
+    }
+  }
+
+  /// Close the queue.  Any further attempts to push nodes fail, and clients
+  /// blocked waiting for space are released.  Nodes already in the queue can
+  /// still be popped; once the queue is empty poppers are told that no more
+  /// nodes will arrive.
+  pub(crate) fn close(&self) {
+    let mut inner = self.lock();
+    if inner.closed {
+      return;
+    }
+    inner.closed = true;
+    let mut wakers = inner.pushers.take_all();
+    wakers.append(&mut inner.poppers.take_all());
+    wakers.append(&mut inner.matchers.take_all());
+    drop(inner);
+
+    self.space.notify_all();
+    for waker in wakers {
+      waker.wake();
+    }
+  }
+
+  /// Returns `true` if there are clients left that can push nodes onto the
+  /// queue.
+  pub(crate) fn has_clients(&self) -> bool {
+    let inner = self.lock();
+    inner.clients > 0
+  }
+
+  /// Returns `true` if the queue has been closed.
+  pub(crate) fn is_closed(&self) -> bool {
+    let inner = self.lock();
+    inner.closed
+  }
+
+  /// Returns `true` if no new nodes will be pushed onto the queue.
+  pub(crate) fn is_finished(&self) -> bool {
+    let inner = self.lock();
+    inner.is_finished()
+  }
+
+  /// Returns `true` if no more nodes will be popped off the queue.
+  pub(crate) fn is_drained(&self) -> bool {
+    let inner = self.lock();
+    inner.is_drained()
+  }
+
+  /// Register another client pushing nodes onto the queue.  Returns an
+  /// identifier for the new client.  The first client, created along with
+  /// the queue, is identified by `0`.
+  pub(crate) fn add_client(&self) -> usize {
+    let mut inner = self.lock();
+    inner.clients += 1;
+    let id = inner.next_client;
+    inner.next_client = inner.next_client.wrapping_add(1);
+    id
+  }
+
+  /// Unregister a client.  Once the last client is gone, no more nodes can
+  /// arrive, so wake all waiting poppers to let them know.
+  pub(crate) fn remove_client(&self) {
+    let mut inner = self.lock();
+    inner.clients -= 1;
+    if inner.clients > 0 {
+      return;
+    }
+    let mut wakers = inner.poppers.take_all();
+    wakers.append(&mut inner.matchers.take_all());
+    drop(inner);
+
+    for waker in wakers {
+      waker.wake();
+    }
+  }
+
+  /// Register another server popping nodes off the queue.
+  pub(crate) fn add_server(&self) {
+    let mut inner = self.lock();
+    inner.servers += 1;
+  }
+
+  /// Unregister a server.  Once the last server is gone the queue is
+  /// disconnected:  Clients blocked waiting for space are released and all
+  /// nodes still in the queue are dropped.
+  pub(crate) fn remove_server(&self) {
+    let mut inner = self.lock();
+    inner.servers -= 1;
+    if inner.servers > 0 {
+      return;
+    }
+    inner.disconnected = true;
+    let wakers = inner.pushers.take_all();
+    let nodes = inner.q.take();
+    drop(inner);
+
+    self.space.notify_all();
+    for waker in wakers {
+      waker.wake();
+    }
+
+    // Drop the nodes without holding the lock
+    drop(nodes);
+  }
+}
+
+
+pub(crate) struct PopFuture<'a, I> {
+  q: &'a NotifyQueue<I>,
+  deadline: Option<Instant>,
+
+  /// Registration with the timer that wakes the task when the deadline
+  /// expires.
+  timer: Option<timer::Registration>,
+
+  /// Identifies this task in the queue's list of waiting poppers.
+  id: Option<usize>
+}
+
+impl<I> Future for PopFuture<'_, I> {
+  type Output = Option<I>;
+  fn poll(self: Pin<&mut Self>, ctx: &mut Context<'_>) -> Poll<Self::Output> {
+    let this = self.get_mut();
+    let res = this.q.poll_pop(&mut this.id, ctx);
+    if let (Poll::Pending, Some(deadline)) = (&res, this.deadline) {
+      if Instant::now() >= deadline {
+        this.q.cancel_pop(&mut this.id);
+        return Poll::Ready(None);
+      }
+      match &this.timer {
+        Some(reg) => reg.update(ctx.waker()),
+        None => this.timer = Some(timer::wake_at(deadline, ctx.waker()))
+      }
+    }
+    res
+  }
+}
+
+impl<I> Drop for PopFuture<'_, I> {
+  fn drop(&mut self) {
+    self.q.cancel_pop(&mut self.id);
+  }
+}
+
+
+pub(crate) struct PopMatchingFuture<'a, I, F> {
+  q: &'a NotifyQueue<I>,
+  pred: F,
+
+  /// Identifies this task in the queue's list of waiting matchers.
+  id: Option<usize>
+}
+
+// The predicate is never pinned; it is only ever called by reference.
+impl<I, F> Unpin for PopMatchingFuture<'_, I, F> {}
+
+impl<I, F> Future for PopMatchingFuture<'_, I, F>
+where
+  F: FnMut(&I) -> bool
+{
+  type Output = Option<I>;
+  fn poll(self: Pin<&mut Self>, ctx: &mut Context<'_>) -> Poll<Self::Output> {
+    let this = self.get_mut();
+    let mut inner = this.q.lock();
+    let node = inner.q.pop_matching(&mut this.pred);
+    if node.is_some() || inner.is_finished() {
+      if let Some(id) = this.id.take() {
+        inner.matchers.remove(id);
+      }
+      if node.is_some() {
+        this.q.popped(inner);
+      }
+      Poll::Ready(node)
+    } else {
+      inner.matchers.register(&mut this.id, ctx.waker());
+      Poll::Pending
+    }
+  }
+}
+
+impl<I, F> Drop for PopMatchingFuture<'_, I, F> {
+  /// Matchers are all woken together, so there's no wakeup to pass on;  just
+  /// leave the list of waiting matchers.
+  fn drop(&mut self) {
+    if let Some(id) = self.id {
+      let mut inner = self.q.lock();
+      inner.matchers.remove(id);
+    }
+  }
+}
+
+
+pub(crate) struct PushFuture<'a, I> {
+  q: &'a NotifyQueue<I>,
+  item: Option<I>,
+  deadline: Option<Instant>,
+
+  /// Registration with the timer that wakes the task when the deadline
+  /// expires.
+  timer: Option<timer::Registration>,
+
+  /// Identifies this task in the queue's list of waiting pushers.
+  id: Option<usize>
+}
+
+// The item is never pinned; it is only ever moved into the queue.
+impl<I> Unpin for PushFuture<'_, I> {}
+
+impl<I: Node> Future for PushFuture<'_, I> {
+  type Output = Result<(), PushError<I>>;
+  fn poll(self: Pin<&mut Self>, ctx: &mut Context<'_>) -> Poll<Self::Output> {
+    let this = self.get_mut();
+    let mut inner = this.q.lock();
+    let expired = match this.deadline {
+      Some(deadline) => Instant::now() >= deadline,
+      None => false
+    };
+    if inner.disconnected || inner.closed || !inner.is_full() || expired {
+      if let Some(id) = this.id.take() {
+        inner.pushers.remove(id);
+      }
+      // Unwrap is okay; the item is only taken when the future completes.
+      let item = match inner.reject(this.item.take().unwrap()) {
+        Ok(item) => item,
+        Err(e) => return Poll::Ready(Err(e))
+      };
+      if inner.is_full() {
+        return Poll::Ready(Err(PushError::Full(item)));
+      }
+      this.q.push_locked(inner, item);
+      Poll::Ready(Ok(()))
+    } else {
+      inner.pushers.register(&mut this.id, ctx.waker());
+      drop(inner);
+      if let Some(deadline) = this.deadline {
+        match &this.timer {
+          Some(reg) => reg.update(ctx.waker()),
+          None => this.timer = Some(timer::wake_at(deadline, ctx.waker()))
+        }
+      }
+      Poll::Pending
+    }
+  }
+}
+
+impl<I> Drop for PushFuture<'_, I> {
+  /// If the future was woken, but is dropped before it got a chance to push
+  /// its node, then pass the wakeup on to another waiting task.
+  fn drop(&mut self) {
+    if let Some(id) = self.id {
+      let mut inner = self.q.lock();
+      if !inner.pushers.remove(id) && !inner.is_full() {
+        let waker = inner.pushers.take_one();
+        drop(inner);
+        wake(waker);
+      }
+    }
+  }
+}
+
+// vim: set ft=rust et sw=2 ts=2 sts=2 cinoptions=2 tw=79 :
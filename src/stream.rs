@@ -0,0 +1,81 @@
+//! Receive incoming messages as a [`Stream`].
+
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use futures_core::stream::{FusedStream, Stream};
+
+use crate::rctx::ReplyContext;
+use crate::server::Server;
+
+/// A [`Stream`] of incoming messages and their reply contexts.
+///
+/// Returned by [`Server::into_stream()`].
+pub struct ServerStream<S, R, E, P = ()> {
+  server: Server<S, R, E, P>,
+
+  /// Identifies this task in the queue's list of waiting servers.
+  id: Option<usize>,
+
+  /// Set once the stream has ended.
+  terminated: bool
+}
+
+impl<S, R, E, P> ServerStream<S, R, E, P> {
+  pub(crate) fn new(server: Server<S, R, E, P>) -> Self {
+    ServerStream {
+      server,
+      id: None,
+      terminated: false
+    }
+  }
+
+  /// Get a reference to the underlying server.
+  pub fn get_ref(&self) -> &Server<S, R, E, P> {
+    &self.server
+  }
+}
+
+impl<S, R: 'static + Send, E, P> Stream for ServerStream<S, R, E, P> {
+  type Item = (S, ReplyContext<R, E, P>);
+
+  fn poll_next(
+    self: Pin<&mut Self>,
+    ctx: &mut Context<'_>
+  ) -> Poll<Option<Self::Item>> {
+    let this = self.get_mut();
+    if this.terminated {
+      return Poll::Ready(None);
+    }
+    loop {
+      match this.server.srvq.poll_pop(&mut this.id, ctx) {
+        Poll::Ready(Some(node)) => {
+          if let Some(incoming) = node.receive() {
+            break Poll::Ready(Some(incoming.into_request()));
+          }
+        }
+        Poll::Ready(None) => {
+          this.terminated = true;
+          break Poll::Ready(None);
+        }
+        Poll::Pending => break Poll::Pending
+      }
+    }
+  }
+}
+
+impl<S, R: 'static + Send, E, P> FusedStream for ServerStream<S, R, E, P> {
+  fn is_terminated(&self) -> bool {
+    self.terminated
+  }
+}
+
+impl<S, R, E, P> Drop for ServerStream<S, R, E, P> {
+  /// If the stream was woken, but is dropped before it got a chance to
+  /// receive a message, then pass the wakeup on to another waiting server.
+  fn drop(&mut self) {
+    self.server.srvq.cancel_pop(&mut self.id);
+  }
+}
+
+// vim: set ft=rust et sw=2 ts=2 sts=2 cinoptions=2 tw=79 :
@@ -0,0 +1,139 @@
+//! Wake tasks when deadlines expire.
+//!
+//! There's no portable way to ask an `async` runtime for a timer, so `async`
+//! calls with deadlines register their wakers with a single, lazily launched,
+//! thread which wakes them once their deadlines have expired.
+
+use std::collections::BTreeMap;
+use std::sync::{Condvar, Mutex, MutexGuard, OnceLock, PoisonError};
+use std::task::Waker;
+use std::thread;
+use std::time::Instant;
+
+struct Inner {
+  next_id: u64,
+  wakers: BTreeMap<(Instant, u64), Waker>
+}
+
+struct Timer {
+  inner: Mutex<Inner>,
+  signal: Condvar
+}
+
+impl Timer {
+  /// Lock the timer's state.  No application code runs while the lock is
+  /// held, so lock poisoning is ignored.
+  fn lock(&self) -> MutexGuard<'_, Inner> {
+    self.inner.lock().unwrap_or_else(PoisonError::into_inner)
+  }
+}
+
+static TIMER: OnceLock<Timer> = OnceLock::new();
+
+fn timer() -> &'static Timer {
+  TIMER.get_or_init(|| {
+    thread::Builder::new()
+      .name("ump-timer".into())
+      .spawn(run)
+      .expect("Unable to launch timer thread");
+    Timer {
+      inner: Mutex::new(Inner {
+        next_id: 0,
+        wakers: BTreeMap::new()
+      }),
+      signal: Condvar::new()
+    }
+  })
+}
+
+fn run() {
+  let timer = timer();
+  let mut expired = Vec::new();
+  loop {
+    let mut inner = timer.lock();
+    let now = Instant::now();
+    let next = loop {
+      match inner.wakers.keys().next().copied() {
+        Some(key) if key.0 <= now => {
+          // Unwrap is okay; the key was just looked up
+          expired.push(inner.wakers.remove(&key).unwrap());
+        }
+        Some(key) => break Some(key.0),
+        None => break None
+      }
+    };
+
+    if expired.is_empty() {
+      // Nothing to wake; wait for the next deadline, or for a new one to be
+      // registered.  The lock is reacquired at the top of the loop.
+      drop(match next {
+        Some(deadline) => {
+          timer
+            .signal
+            .wait_timeout(inner, deadline - now)
+            .unwrap_or_else(PoisonError::into_inner)
+            .0
+        }
+        None => timer
+          .signal
+          .wait(inner)
+          .unwrap_or_else(PoisonError::into_inner)
+      });
+    } else {
+      // Wake the tasks without holding the lock
+      drop(inner);
+      for waker in expired.drain(..) {
+        waker.wake();
+      }
+    }
+  }
+}
+
+/// Handle to a registered waker.  Dropping it cancels the registration.
+pub(crate) struct Registration {
+  key: (Instant, u64)
+}
+
+impl Registration {
+  /// Update the waker to be woken, if it has changed.
+  pub(crate) fn update(&self, waker: &Waker) {
+    let mut inner = timer().lock();
+    if let Some(w) = inner.wakers.get_mut(&self.key) {
+      if !w.will_wake(waker) {
+        *w = waker.clone();
+      }
+    }
+  }
+}
+
+impl Drop for Registration {
+  fn drop(&mut self) {
+    let mut inner = timer().lock();
+    inner.wakers.remove(&self.key);
+  }
+}
+
+/// Wake `waker` once `deadline` has expired, unless the returned
+/// registration has been dropped before then.
+pub(crate) fn wake_at(deadline: Instant, waker: &Waker) -> Registration {
+  let timer = timer();
+  let mut inner = timer.lock();
+  let key = (deadline, inner.next_id);
+  inner.next_id = inner.next_id.wrapping_add(1);
+  let earliest = match inner.wakers.keys().next() {
+    Some(first) => key < *first,
+    None => true
+  };
+  inner.wakers.insert(key, waker.clone());
+  drop(inner);
+
+  // Only the timer thread waits on the condvar, and it only needs to
+  // recalculate its timeout if the new deadline is the earliest one.
+  if earliest {
+    timer.signal.notify_one();
+  }
+
+  Registration { key }
+}
+
+// vim: set ft=rust et sw=2 ts=2 sts=2 cinoptions=2 tw=79 :
@@ -0,0 +1,224 @@
+//! Wakeup primitives shared by the server queue and the reply contexts.
+//!
+//! Blocking calls wait by parking their thread, and are woken through a
+//! [`Waker`] that unparks it.  This lets blocked threads and waiting `async`
+//! tasks be registered, and woken, in the same way.
+
+use std::cell::UnsafeCell;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::task::{Wake, Waker};
+use std::thread::{self, Thread};
+
+/// Wakes a thread that is parked in a blocking call.
+struct ThreadWaker(Thread);
+
+impl Wake for ThreadWaker {
+  fn wake(self: Arc<Self>) {
+    self.0.unpark();
+  }
+
+  fn wake_by_ref(self: &Arc<Self>) {
+    self.0.unpark();
+  }
+}
+
+thread_local! {
+  static THREAD_WAKER: Waker =
+    Waker::from(Arc::new(ThreadWaker(thread::current())));
+}
+
+/// Returns a [`Waker`] which unparks the current thread.
+///
+/// The waker is created once per thread, so blocking calls don't need to
+/// allocate one each time they wait.
+pub(crate) fn current_thread() -> Waker {
+  THREAD_WAKER
+    .try_with(Waker::clone)
+    .unwrap_or_else(|_| Waker::from(Arc::new(ThreadWaker(thread::current()))))
+}
+
+
+/// Tasks waiting for a condition.  Tasks are woken in the order they started
+/// waiting.
+pub(crate) struct Waiters {
+  next_id: usize,
+  list: VecDeque<(usize, Waker)>
+}
+
+impl Waiters {
+  pub(crate) fn new() -> Self {
+    Waiters {
+      next_id: 0,
+      list: VecDeque::new()
+    }
+  }
+
+  /// Register a task's waker.  If `id` refers to a task that is already
+  /// waiting its waker is updated in place, otherwise it is appended to the
+  /// list and `id` is updated to refer to it.
+  pub(crate) fn register(&mut self, id: &mut Option<usize>, waker: &Waker) {
+    if let Some(id) = *id {
+      if let Some(entry) = self.list.iter_mut().find(|(i, _)| *i == id) {
+        if !entry.1.will_wake(waker) {
+          entry.1 = waker.clone();
+        }
+        return;
+      }
+    }
+    let new_id = self.next_id;
+    self.next_id = self.next_id.wrapping_add(1);
+    self.list.push_back((new_id, waker.clone()));
+    *id = Some(new_id);
+  }
+
+  /// Remove a task from the list.  Returns `false` if it was no longer in the
+  /// list, which means that it has been woken.
+  pub(crate) fn remove(&mut self, id: usize) -> bool {
+    if let Some(idx) = self.list.iter().position(|(i, _)| *i == id) {
+      self.list.remove(idx);
+      true
+    } else {
+      false
+    }
+  }
+
+  /// Take the waker of the task that has been waiting the longest.
+  pub(crate) fn take_one(&mut self) -> Option<Waker> {
+    self.list.pop_front().map(|(_, waker)| waker)
+  }
+
+  /// Take the wakers of the (up to) `n` tasks that have been waiting the
+  /// longest.
+  pub(crate) fn take(&mut self, n: usize) -> Vec<Waker> {
+    let n = n.min(self.list.len());
+    self.list.drain(..n).map(|(_, waker)| waker).collect()
+  }
+
+  /// Take the wakers of all waiting tasks.
+  pub(crate) fn take_all(&mut self) -> Vec<Waker> {
+    self.list.drain(..).map(|(_, waker)| waker).collect()
+  }
+}
+
+
+/// No task is registering or waking the waker.
+const IDLE: usize = 0;
+
+/// A task is registering its waker.
+const REGISTERING: usize = 0b01;
+
+/// The registered waker is being taken to be woken.
+const WAKING: usize = 0b10;
+
+/// A slot holding the [`Waker`] of a single waiting task, which can be
+/// registered and woken without taking a lock.
+///
+/// Only one waker is kept, but any number of threads may wake it
+/// concurrently.  If the waker is woken while it is being registered, the
+/// registering task is woken immediately instead, so a wakeup is never lost:
+/// A task that registers its waker and then checks for the condition it's
+/// waiting for will either see the condition, or be woken once it has been
+/// met.  The same goes for a task that registers its waker while another
+/// task is registering one.
+pub(crate) struct AtomicWaker {
+  state: AtomicUsize,
+  waker: UnsafeCell<Option<Waker>>
+}
+
+// The waker is only accessed by the thread that has moved `state` out of
+// `IDLE`, which acts as a lock.
+unsafe impl Send for AtomicWaker {}
+unsafe impl Sync for AtomicWaker {}
+
+impl AtomicWaker {
+  pub(crate) fn new() -> Self {
+    AtomicWaker {
+      state: AtomicUsize::new(IDLE),
+      waker: UnsafeCell::new(None)
+    }
+  }
+
+  /// Register the waker to wake on the next call to [`AtomicWaker::wake()`],
+  /// replacing any previously registered one.
+  pub(crate) fn register(&self, waker: &Waker) {
+    match self.state.compare_exchange(
+      IDLE,
+      REGISTERING,
+      Ordering::Acquire,
+      Ordering::Acquire
+    ) {
+      Ok(_) => {
+        // SAFETY: Holding the `REGISTERING` state grants exclusive access to
+        // the waker.
+        unsafe {
+          let slot = &mut *self.waker.get();
+          match slot {
+            Some(old) if old.will_wake(waker) => {}
+            _ => *slot = Some(waker.clone())
+          }
+        }
+        if self
+          .state
+          .compare_exchange(
+            REGISTERING,
+            IDLE,
+            Ordering::AcqRel,
+            Ordering::Acquire
+          )
+          .is_err()
+        {
+          // The waker was woken while it was being registered; the waking
+          // thread left it for us to wake.
+          //
+          // SAFETY: The waking thread does not touch the waker when it finds
+          // the `REGISTERING` state set.
+          let waker = unsafe { (*self.waker.get()).take() };
+          self.state.swap(IDLE, Ordering::AcqRel);
+          if let Some(waker) = waker {
+            waker.wake();
+          }
+        }
+      }
+      Err(WAKING) => {
+        // Currently being woken;  the old waker may be the one being woken,
+        // so wake the new one to be on the safe side.
+        waker.wake_by_ref();
+      }
+      Err(_) => {
+        // Another task is registering its waker.  There's only room for one,
+        // so wake this one to have its task check its condition again rather
+        // than leave it waiting without a registered waker.
+        waker.wake_by_ref();
+      }
+    }
+  }
+
+  /// Wake the registered waker, if any.
+  pub(crate) fn wake(&self) {
+    if let Some(waker) = self.take() {
+      waker.wake();
+    }
+  }
+
+  /// Take the registered waker, if any, so that it can be woken.
+  pub(crate) fn take(&self) -> Option<Waker> {
+    match self.state.fetch_or(WAKING, Ordering::AcqRel) {
+      IDLE => {
+        // SAFETY: Moving out of `IDLE` grants exclusive access to the waker.
+        let waker = unsafe { (*self.waker.get()).take() };
+        self.state.fetch_and(!WAKING, Ordering::Release);
+        waker
+      }
+      _ => {
+        // Either another thread is already waking the waker, or a waker is
+        // being registered, in which case the registering thread will wake
+        // it.
+        None
+      }
+    }
+  }
+}
+
+// vim: set ft=rust et sw=2 ts=2 sts=2 cinoptions=2 tw=79 :
@@ -21,7 +21,7 @@ fn async_client() {
   let (server, client) = channel::<Request, Reply, ()>();
 
   let server_thread = thread::spawn(move || loop {
-    let (req, rctx) = server.wait();
+    let (req, rctx) = server.wait().unwrap();
     match req {
       Request::Add(a, b) => rctx.reply(Reply::Sum(a + b)).unwrap(),
       Request::Croak => {
@@ -55,4 +55,50 @@ fn async_client() {
   server_thread.join().unwrap();
 }
 
+
+#[test]
+fn async_client_many_pending() {
+  let tokrt = tokio::runtime::Builder::new_current_thread()
+    .build()
+    .unwrap();
+
+  let ntasks = 512;
+
+  let (server, client) = channel::<Request, Reply, ()>();
+
+  // Collect all the requests before replying to any of them, so that every
+  // task's reply future is pending at the same time.
+  let server_thread = thread::spawn(move || {
+    let mut pending = Vec::new();
+    for _ in 0..ntasks {
+      pending.push(server.wait().unwrap());
+    }
+    for (req, rctx) in pending {
+      if let Request::Add(a, b) = req {
+        rctx.reply(Reply::Sum(a + b)).unwrap();
+      }
+    }
+  });
+
+  tokrt.block_on(async {
+    let mut handles = Vec::new();
+    for i in 0..ntasks {
+      let client = client.clone();
+      handles.push(tokio::spawn(async move {
+        let result = client.asend(Request::Add(i, i)).await.unwrap();
+        if let Reply::Sum(sum) = result {
+          assert_eq!(sum, i + i);
+        } else {
+          panic!("Didn't get sum");
+        }
+      }));
+    }
+    for h in handles {
+      h.await.unwrap();
+    }
+  });
+
+  server_thread.join().unwrap();
+}
+
 // vim: set ft=rust et sw=2 ts=2 sts=2 cinoptions=2 tw=79 :
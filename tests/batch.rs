@@ -0,0 +1,208 @@
+// Make sure that servers can receive messages in batches, in the order they
+// were sent, and that lingering servers wait for batches to fill up.
+use std::thread;
+use std::time::{Duration, Instant};
+
+use ump::{channel, channel_bounded, Closed};
+
+#[test]
+fn up_to_max_in_order() {
+  let (server, client) = channel::<u32, u32, ()>();
+
+  let pending: Vec<_> = (0..10).map(|n| client.post(n).unwrap()).collect();
+  drop(client);
+
+  let mut sizes = Vec::new();
+  let mut received = Vec::new();
+  while let Some(batch) = server.wait_batch(4) {
+    sizes.push(batch.len());
+    for (n, rctx) in batch {
+      received.push(n);
+      rctx.reply(n).unwrap();
+    }
+  }
+  assert_eq!(sizes, vec![4, 4, 2]);
+  assert_eq!(received, (0..10).collect::<Vec<u32>>());
+
+  for (n, wrply) in pending.into_iter().enumerate() {
+    assert_eq!(wrply.wait().unwrap(), n as u32);
+  }
+}
+
+
+#[test]
+fn skip_cancelled() {
+  let (server, client) = channel::<u32, u32, ()>();
+
+  let first = client.post(1).unwrap();
+  drop(client.post(2).unwrap());
+  let third = client.post(3).unwrap();
+
+  let batch = server.try_wait_batch(8).unwrap().unwrap();
+  let msgs: Vec<u32> = batch.iter().map(|(n, _)| *n).collect();
+  assert_eq!(msgs, vec![1, 3]);
+  for (n, rctx) in batch {
+    rctx.reply(n).unwrap();
+  }
+  assert_eq!(first.wait().unwrap(), 1);
+  assert_eq!(third.wait().unwrap(), 3);
+
+  assert!(matches!(server.try_wait_batch(8), Ok(None)));
+  drop(client);
+  assert!(matches!(server.try_wait_batch(8), Err(Closed)));
+}
+
+
+#[test]
+fn bounded_queue() {
+  let (server, client) = channel_bounded::<u32, u32, ()>(3);
+
+  // Popping a batch must make room for all of the clients waiting for space
+  // in the queue.
+  let client_threads: Vec<_> = (0..4)
+    .map(|t| {
+      let client = client.clone();
+      thread::spawn(move || {
+        for n in 0..25 {
+          let n = t * 100 + n;
+          assert_eq!(client.send(n).unwrap(), n);
+        }
+      })
+    })
+    .collect();
+  drop(client);
+
+  let mut nreceived = 0;
+  while let Some(batch) = server.wait_batch(3) {
+    assert!(batch.len() <= 3);
+    for (n, rctx) in batch {
+      nreceived += 1;
+      rctx.reply(n).unwrap();
+    }
+  }
+  assert_eq!(nreceived, 100);
+
+  for t in client_threads {
+    t.join().unwrap();
+  }
+}
+
+
+#[test]
+fn linger_until_full() {
+  let (server, client) = channel::<u32, u32, ()>();
+  let observer = server.clone();
+
+  // The batch is returned as soon as it is full, long before the linger time
+  // has elapsed.
+  let server_thread = thread::spawn(move || {
+    let start = Instant::now();
+    let batch = server
+      .wait_batch_linger(4, Duration::from_secs(10))
+      .unwrap();
+    assert!(start.elapsed() < Duration::from_secs(5));
+    assert_eq!(batch.len(), 4);
+    for (n, rctx) in batch {
+      rctx.reply(n).unwrap();
+    }
+  });
+
+  // Send the rest of the batch once the server has received the first
+  // message, and is lingering for more.
+  let mut pending = vec![client.post(0).unwrap()];
+  while !observer.was_empty() {
+    thread::yield_now();
+  }
+  for n in 1..4 {
+    pending.push(client.post(n).unwrap());
+  }
+  for (n, wrply) in pending.into_iter().enumerate() {
+    assert_eq!(wrply.wait().unwrap(), n as u32);
+  }
+
+  server_thread.join().unwrap();
+}
+
+
+#[test]
+fn linger_expires() {
+  let (server, client) = channel::<u32, u32, ()>();
+
+  let wrply = client.post(1).unwrap();
+
+  let start = Instant::now();
+  let batch = server
+    .wait_batch_linger(4, Duration::from_millis(100))
+    .unwrap();
+  assert!(start.elapsed() >= Duration::from_millis(100));
+  assert_eq!(batch.len(), 1);
+  for (n, rctx) in batch {
+    rctx.reply(n).unwrap();
+  }
+  assert_eq!(wrply.wait().unwrap(), 1);
+}
+
+
+#[test]
+fn linger_ends_without_clients() {
+  let (server, client) = channel::<u32, u32, ()>();
+
+  let wrply = client.post(1).unwrap();
+  drop(client);
+
+  // No more messages can arrive, so there's no point lingering.
+  let start = Instant::now();
+  let batch = server
+    .wait_batch_linger(4, Duration::from_secs(10))
+    .unwrap();
+  assert!(start.elapsed() < Duration::from_secs(5));
+  assert_eq!(batch.len(), 1);
+  drop(batch);
+  assert!(wrply.wait().is_err());
+
+  assert!(server.wait_batch(4).is_none());
+}
+
+
+#[test]
+fn async_wait_batch() {
+  let tokrt = tokio::runtime::Runtime::new().unwrap();
+
+  let (server, client) = channel::<u32, u32, ()>();
+
+  let client_thread = thread::spawn(move || {
+    let replies: Result<Vec<u32>, _> =
+      client.send_many(0..6).into_iter().collect();
+    assert_eq!(replies.unwrap(), (0..6).map(|n| n * 2).collect::<Vec<_>>());
+
+    // Send one more once the first batch has been handled, for the server to
+    // linger for.
+    assert_eq!(client.send(6).unwrap(), 12);
+  });
+
+  tokrt.block_on(async {
+    // The whole batch was queued at once.
+    let batch = server.async_wait_batch(8).await.unwrap();
+    assert_eq!(batch.len(), 6);
+    for (n, rctx) in batch {
+      rctx.reply(n * 2).unwrap();
+    }
+
+    let start = Instant::now();
+    let batch = server
+      .async_wait_batch_linger(8, Duration::from_millis(100))
+      .await
+      .unwrap();
+    assert!(start.elapsed() >= Duration::from_millis(100));
+    assert_eq!(batch.len(), 1);
+    for (n, rctx) in batch {
+      rctx.reply(n * 2).unwrap();
+    }
+
+    assert!(server.async_wait_batch(8).await.is_none());
+  });
+
+  client_thread.join().unwrap();
+}
+
+// vim: set ft=rust et sw=2 ts=2 sts=2 cinoptions=2 tw=79 :
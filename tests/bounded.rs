@@ -0,0 +1,163 @@
+// Make sure that bounded channels apply backpressure to clients.
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+use ump::{channel_bounded, Error};
+
+#[test]
+fn try_send_queue_full() {
+  let (server, client) = channel_bounded::<String, String, ()>(1);
+  let (tx, rx) = mpsc::channel();
+
+  // Fill the queue with a request the server isn't looking at yet.
+  let client_clone = client.clone();
+  let client_thread = thread::spawn(move || {
+    let reply = client_clone.send(String::from("First")).unwrap();
+    assert_eq!(reply, "Hello, First!");
+  });
+  while server.was_empty() {
+    thread::yield_now();
+  }
+
+  match client.try_send(String::from("Second")) {
+    Err(Error::QueueFull(msg)) => {
+      // This is the expected error; the message is handed back
+      assert_eq!(msg, "Second");
+    }
+    _ => {
+      panic!("Unexpected return value");
+    }
+  }
+
+  let server_thread = thread::spawn(move || {
+    for _ in 0..2 {
+      let (data, rctx) = server.wait().unwrap();
+      rctx.reply(format!("Hello, {}!", data)).unwrap();
+    }
+    tx.send(()).unwrap();
+  });
+
+  client_thread.join().unwrap();
+
+  // There's space in the queue again
+  let reply = client.try_send(String::from("Second")).unwrap();
+  assert_eq!(reply, "Hello, Second!");
+
+  rx.recv().unwrap();
+  server_thread.join().unwrap();
+}
+
+
+#[test]
+fn send_blocks_until_space() {
+  let (server, client) = channel_bounded::<u32, u32, ()>(2);
+  let nclients = 8;
+
+  let mut handles = Vec::new();
+  for i in 0..nclients {
+    let client = client.clone();
+    handles.push(thread::spawn(move || {
+      assert_eq!(client.send(i).unwrap(), i * 2);
+    }));
+  }
+
+  for _ in 0..nclients {
+    let (n, rctx) = server.wait().unwrap();
+    rctx.reply(n * 2).unwrap();
+  }
+
+  for h in handles {
+    h.join().unwrap();
+  }
+}
+
+
+#[test]
+fn send_timeout_queue_full() {
+  let (server, client) = channel_bounded::<String, String, ()>(1);
+
+  let client_clone = client.clone();
+  let client_thread = thread::spawn(move || {
+    let _ = client_clone.send(String::from("First"));
+  });
+  while server.was_empty() {
+    thread::yield_now();
+  }
+
+  match client.send_timeout(String::from("Second"), Duration::from_millis(50))
+  {
+    Err(Error::QueueFull(msg)) => {
+      assert_eq!(msg, "Second");
+    }
+    _ => {
+      panic!("Unexpected return value");
+    }
+  }
+
+  drop(server);
+  client_thread.join().unwrap();
+}
+
+
+#[test]
+fn async_send_waits_for_space() {
+  let tokrt = tokio::runtime::Runtime::new().unwrap();
+
+  let (server, client) = channel_bounded::<u32, u32, ()>(1);
+  let niterations = 64;
+
+  let server_thread = thread::spawn(move || {
+    for _ in 0..niterations {
+      let (n, rctx) = server.wait().unwrap();
+      rctx.reply(n + 1).unwrap();
+    }
+  });
+
+  tokrt.block_on(async {
+    let mut handles = Vec::new();
+    for i in 0..niterations {
+      let client = client.clone();
+      handles.push(tokio::spawn(async move {
+        assert_eq!(client.asend(i).await.unwrap(), i + 1);
+      }));
+    }
+    for h in handles {
+      h.await.unwrap();
+    }
+  });
+
+  server_thread.join().unwrap();
+}
+
+
+#[test]
+fn server_drop_releases_blocked_clients() {
+  let (server, client) = channel_bounded::<String, String, ()>(1);
+
+  let client_clone = client.clone();
+  let first = thread::spawn(move || client_clone.send(String::from("First")));
+  while server.was_empty() {
+    thread::yield_now();
+  }
+  let client_clone = client.clone();
+  let (sending_tx, sending_rx) = mpsc::channel();
+  let second = thread::spawn(move || {
+    sending_tx.send(()).unwrap();
+    client_clone.send(String::from("Second"))
+  });
+
+  // The message is handed back whether or not the client had started waiting
+  // for room by the time the server was dropped.
+  sending_rx.recv().unwrap();
+  drop(server);
+
+  for (h, expect) in [(first, "First"), (second, "Second")] {
+    match h.join().unwrap() {
+      Err(Error::ServerDisappeared(msg)) => assert_eq!(msg, expect),
+      _ => panic!("Unexpected return value")
+    }
+  }
+}
+
+// vim: set ft=rust et sw=2 ts=2 sts=2 cinoptions=2 tw=79 :
@@ -0,0 +1,157 @@
+// Make sure that dropping an asend() future withdraws the request.
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+use futures::stream::{FuturesUnordered, StreamExt};
+
+use ump::{channel, ReplyError};
+
+#[test]
+fn skip_cancelled_in_queue() {
+  let tokrt = tokio::runtime::Runtime::new().unwrap();
+
+  let (server, client) = channel::<String, String, ()>();
+
+  // Give up on the request while the server isn't looking at its queue.
+  tokrt.block_on(async {
+    let res = tokio::time::timeout(
+      Duration::from_millis(50),
+      client.asend(String::from("Cancelled"))
+    )
+    .await;
+    assert!(res.is_err());
+  });
+
+  let server_thread = thread::spawn(move || {
+    // The cancelled request must be skipped.
+    let (data, rctx) = server.wait().unwrap();
+    assert_eq!(data, "Client");
+    rctx.reply(format!("Hello, {}!", data)).unwrap();
+  });
+
+  let reply = client.send(String::from("Client")).unwrap();
+  assert_eq!(reply, "Hello, Client!");
+
+  server_thread.join().unwrap();
+}
+
+
+#[test]
+fn handler_sees_cancellation() {
+  let tokrt = tokio::runtime::Runtime::new().unwrap();
+
+  let (server, client) = channel::<String, String, ()>();
+  let (picked_tx, picked_rx) = mpsc::channel();
+  let (dropped_tx, dropped_rx) = mpsc::channel();
+
+  let server_thread = thread::spawn(move || {
+    let (_, rctx) = server.wait().unwrap();
+    assert!(!rctx.is_cancelled());
+    picked_tx.send(()).unwrap();
+
+    // Wait for the client to drop its request future.
+    dropped_rx.recv().unwrap();
+    assert!(rctx.is_cancelled());
+  });
+
+  tokrt.block_on(async {
+    let fut = client.asend(String::from("Client"));
+    tokio::select! {
+      _ = fut => panic!("Unexpected reply"),
+      _ = tokio::task::spawn_blocking(move || picked_rx.recv().unwrap()) => {}
+    }
+  });
+  dropped_tx.send(()).unwrap();
+
+  server_thread.join().unwrap();
+}
+
+
+#[test]
+fn handler_notified_of_cancellation() {
+  let tokrt = tokio::runtime::Runtime::new().unwrap();
+
+  let (server, client) = channel::<String, String, ()>();
+
+  let server_thread = thread::spawn(move || {
+    let (_, rctx) = server.wait().unwrap();
+    let tokrt = tokio::runtime::Runtime::new().unwrap();
+    tokrt.block_on(async {
+      rctx.cancelled().await;
+    });
+    assert!(rctx.is_cancelled());
+  });
+
+  tokrt.block_on(async {
+    let res = tokio::time::timeout(
+      Duration::from_millis(100),
+      client.asend(String::from("Client"))
+    )
+    .await;
+    assert!(res.is_err());
+  });
+
+  server_thread.join().unwrap();
+}
+
+
+#[test]
+fn several_tasks_notified_of_cancellation() {
+  let tokrt = tokio::runtime::Runtime::new().unwrap();
+
+  let (server, client) = channel::<String, String, ()>();
+  let (picked_tx, picked_rx) = tokio::sync::oneshot::channel();
+
+  let server_thread = thread::spawn(move || {
+    let (_, rctx) = server.wait().unwrap();
+    let tokrt = tokio::runtime::Runtime::new().unwrap();
+    tokrt.block_on(async {
+      // Every waiter must be woken, not just the one that registered last.
+      // Each future in the set is polled with its own waker.
+      let mut waiters: FuturesUnordered<_> =
+        (0..2).map(|_| rctx.cancelled()).collect();
+      let all = async { while waiters.next().await.is_some() {} };
+      let abandoned = rctx.cancelled();
+      let probe = async {
+        tokio::select! {
+          _ = abandoned => panic!("Cancelled too early"),
+          _ = tokio::task::yield_now() => {}
+        }
+        picked_tx.send(()).unwrap();
+      };
+      tokio::join!(all, probe);
+    });
+  });
+
+  tokrt.block_on(async {
+    // Give up on the request once the server is waiting for it to be
+    // cancelled.
+    let reply = client.asend(String::from("Client"));
+    tokio::pin!(reply);
+    tokio::select! {
+      _ = &mut reply => panic!("Unexpected reply"),
+      res = picked_rx => res.unwrap()
+    }
+  });
+
+  server_thread.join().unwrap();
+}
+
+
+#[test]
+fn fail_returns_undelivered_error() {
+  let (server, client) = channel::<String, String, String>();
+
+  // Drop the reply handle before the server gets around to the request.
+  let wrply = client.post(String::from("Client")).unwrap();
+  let (_, rctx) = server.try_wait().unwrap().unwrap();
+  drop(wrply);
+
+  match rctx.fail(String::from("Nobody listens")) {
+    Err(ReplyError::Cancelled(err)) => assert_eq!(err, "Nobody listens"),
+    _ => panic!("Undelivered error was not returned")
+  }
+}
+
+// vim: set ft=rust et sw=2 ts=2 sts=2 cinoptions=2 tw=79 :
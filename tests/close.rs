@@ -0,0 +1,120 @@
+// Make sure that closing a server rejects new requests, but lets the server
+// drain its queue.
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+use ump::{channel, channel_bounded, Closed, Error};
+
+#[test]
+fn drain_after_close() {
+  let (server, client) = channel::<u32, u32, ()>();
+
+  let pending: Vec<_> = (0..4).map(|n| client.post(n).unwrap()).collect();
+  server.close();
+  assert!(server.is_closed());
+
+  match client.send(4) {
+    Err(Error::ServerClosed(n)) => assert_eq!(n, 4),
+    res => panic!("Unexpected result {:?}", res)
+  }
+  match client.try_send(5) {
+    Err(Error::ServerClosed(n)) => assert_eq!(n, 5),
+    res => panic!("Unexpected result {:?}", res)
+  }
+
+  let server_thread = thread::spawn(move || {
+    let mut count = 0;
+    while let Some((n, rctx)) = server.wait() {
+      rctx.reply(n * 2).unwrap();
+      count += 1;
+    }
+    count
+  });
+
+  let replies: Vec<u32> =
+    pending.into_iter().map(|w| w.wait().unwrap()).collect();
+  assert_eq!(replies, vec![0, 2, 4, 6]);
+  assert_eq!(server_thread.join().unwrap(), 4);
+}
+
+
+#[test]
+fn close_wakes_waiting_servers() {
+  let (server, _client) = channel::<u32, u32, ()>();
+  let (waiting_tx, waiting_rx) = mpsc::channel();
+
+  let sync_server = server.clone();
+  let sync_waiting = waiting_tx.clone();
+  let sync_thread = thread::spawn(move || {
+    sync_waiting.send(()).unwrap();
+    sync_server.wait().is_none()
+  });
+
+  let async_server = server.clone();
+  let async_thread = thread::spawn(move || {
+    let tokrt = tokio::runtime::Runtime::new().unwrap();
+    tokrt.block_on(async {
+      let wait = async_server.async_wait();
+      tokio::pin!(wait);
+      assert!(futures::poll!(&mut wait).is_pending());
+      waiting_tx.send(()).unwrap();
+      wait.await.is_none()
+    })
+  });
+
+  // The servers return once closed, whether or not they had started waiting
+  // by then.
+  waiting_rx.recv().unwrap();
+  waiting_rx.recv().unwrap();
+  server.close();
+
+  assert!(sync_thread.join().unwrap());
+  assert!(async_thread.join().unwrap());
+  let res = server.wait_timeout(Duration::from_secs(10));
+  assert!(matches!(res, Err(Closed)));
+}
+
+
+#[test]
+fn close_releases_blocked_clients() {
+  let tokrt = tokio::runtime::Runtime::new().unwrap();
+
+  let (server, client) = channel_bounded::<u32, u32, ()>(1);
+  let (sending_tx, sending_rx) = mpsc::channel();
+
+  let first = client.post(1).unwrap();
+  let sync_client = client.clone();
+  let client_thread = thread::spawn(move || {
+    sending_tx.send(()).unwrap();
+    sync_client.send(2)
+  });
+
+  tokrt.block_on(async {
+    // The queue is full, so the client waits for room.
+    let send = client.asend(3);
+    tokio::pin!(send);
+    assert!(futures::poll!(&mut send).is_pending());
+
+    sending_rx.recv().unwrap();
+    server.close();
+
+    match send.await {
+      Err(Error::ServerClosed(n)) => assert_eq!(n, 3),
+      res => panic!("Unexpected result {:?}", res)
+    }
+  });
+
+  // The message is turned away, whether or not the client had started
+  // waiting for room by the time the server was closed.
+  match client_thread.join().unwrap() {
+    Err(Error::ServerClosed(n)) => assert_eq!(n, 2),
+    res => panic!("Unexpected result {:?}", res)
+  }
+
+  let (n, rctx) = server.wait().unwrap();
+  rctx.reply(n * 2).unwrap();
+  assert_eq!(first.wait().unwrap(), 2);
+}
+
+// vim: set ft=rust et sw=2 ts=2 sts=2 cinoptions=2 tw=79 :
@@ -0,0 +1,132 @@
+// Make sure that replies to posted messages can be collected later.
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+use ump::{channel, Error};
+
+fn doubler() -> (ump::Client<u32, u32, ()>, thread::JoinHandle<()>) {
+  let (server, client) = channel::<u32, u32, ()>();
+  let server_thread = thread::spawn(move || {
+    // Reply in reverse order, to make sure replies are matched up with the
+    // correct requests.
+    let mut pending = Vec::new();
+    for _ in 0..2 {
+      pending.push(server.wait().unwrap());
+    }
+    while let Some((n, rctx)) = pending.pop() {
+      rctx.reply(n * 2).unwrap();
+    }
+  });
+  (client, server_thread)
+}
+
+#[test]
+fn scatter_gather() {
+  let (client1, server1) = doubler();
+  let (client2, server2) = doubler();
+
+  let wr = vec![
+    client1.post(1).unwrap(),
+    client2.post(2).unwrap(),
+    client1.post(3).unwrap(),
+    client2.post(4).unwrap(),
+  ];
+  let replies: Vec<u32> = wr.into_iter().map(|w| w.wait().unwrap()).collect();
+  assert_eq!(replies, [2, 4, 6, 8]);
+
+  server1.join().unwrap();
+  server2.join().unwrap();
+}
+
+
+#[test]
+fn try_get() {
+  let (server, client) = channel::<u32, u32, ()>();
+
+  let wr = client.post(21).unwrap();
+  let wr = wr.try_get().unwrap_err();
+
+  let (n, rctx) = server.wait().unwrap();
+  let wr = wr.try_get().unwrap_err();
+  rctx.reply(n * 2).unwrap();
+
+  match wr.try_get() {
+    Ok(reply) => assert_eq!(reply.unwrap(), 42),
+    Err(_) => panic!("Expected a reply")
+  }
+}
+
+
+#[test]
+fn try_get_then_wait() {
+  let (server, client) = channel::<u32, u32, ()>();
+
+  let wr = client.post(21).unwrap();
+  let wr = wr.try_get().unwrap_err();
+
+  let server_thread = thread::spawn(move || {
+    let (n, rctx) = server.wait().unwrap();
+    rctx.reply(n * 2).unwrap();
+  });
+
+  // A handle that was handed back can still be waited on.
+  assert_eq!(wr.wait().unwrap(), 42);
+
+  server_thread.join().unwrap();
+}
+
+
+#[test]
+fn wait_timeout() {
+  let (server, client) = channel::<u32, u32, ()>();
+
+  let wr = client.post(21).unwrap();
+  match wr.wait_timeout(Duration::from_millis(50)) {
+    Err(Error::Timeout) => {}
+    _ => panic!("Unexpected return value")
+  }
+
+  // The request was cancelled, so the server will never see it.
+  assert!(matches!(server.try_wait(), Ok(None)));
+}
+
+
+#[test]
+fn await_handle() {
+  let tokrt = tokio::runtime::Runtime::new().unwrap();
+  let (client, server_thread) = doubler();
+
+  tokrt.block_on(async {
+    let first = client.post(1).unwrap();
+    let second = client.post(2).unwrap();
+    assert_eq!(second.await.unwrap(), 4);
+    assert_eq!(first.await.unwrap(), 2);
+  });
+
+  server_thread.join().unwrap();
+}
+
+
+#[test]
+fn drop_cancels() {
+  let (server, client) = channel::<u32, u32, ()>();
+  let (picked_tx, picked_rx) = mpsc::channel();
+  let (dropped_tx, dropped_rx) = mpsc::channel();
+
+  let server_thread = thread::spawn(move || {
+    let (_, rctx) = server.wait().unwrap();
+    picked_tx.send(()).unwrap();
+    dropped_rx.recv().unwrap();
+    assert!(rctx.is_cancelled());
+  });
+
+  let wr = client.post(1).unwrap();
+  picked_rx.recv().unwrap();
+  drop(wr);
+  dropped_tx.send(()).unwrap();
+
+  server_thread.join().unwrap();
+}
+
+// vim: set ft=rust et sw=2 ts=2 sts=2 cinoptions=2 tw=79 :
@@ -9,12 +9,13 @@ enum MyError {
 }
 
 #[test]
+#[allow(clippy::useless_conversion)]
 fn sync_expect_noreply() {
   let (server, client) = channel::<String, String, MyError>();
 
   let server_thread = thread::spawn(move || {
     // Wait for data to arrive from a client
-    let (_, rctx) = server.wait();
+    let (_, rctx) = server.wait().unwrap();
 
     rctx.fail(MyError::SomeError("failed".to_string())).unwrap();
   });
@@ -42,7 +43,7 @@ fn async_expect_noreply() {
 
   let server_thread = thread::spawn(move || {
     // Wait for data to arrive from a client
-    let (_, rctx) = server.wait();
+    let (_, rctx) = server.wait().unwrap();
 
     rctx.fail(MyError::SomeError("failed".to_string())).unwrap();
   });
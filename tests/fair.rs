@@ -0,0 +1,128 @@
+// Make sure that fair channels take turns receiving messages from clients.
+use std::thread;
+
+use ump::{fair_channel, fair_channel_bounded};
+
+#[test]
+fn round_robin() {
+  let (server, a) = fair_channel::<(char, u32), (), ()>();
+  let b = a.clone();
+  let c = a.clone();
+
+  let mut pending = Vec::new();
+  for n in 0..3 {
+    pending.push(a.post(('a', n)).unwrap());
+  }
+  pending.push(b.post(('b', 0)).unwrap());
+  for n in 0..2 {
+    pending.push(c.post(('c', n)).unwrap());
+  }
+
+  let mut order = Vec::new();
+  while let Ok(Some((msg, rctx))) = server.try_wait() {
+    order.push(msg);
+    rctx.reply(()).unwrap();
+  }
+  assert_eq!(
+    order,
+    vec![('a', 0), ('b', 0), ('c', 0), ('a', 1), ('c', 1), ('a', 2)]
+  );
+
+  for wrply in pending {
+    wrply.wait().unwrap();
+  }
+}
+
+
+#[test]
+fn weighted() {
+  let (server, mut a) = fair_channel::<(char, u32), (), ()>();
+  let b = a.clone();
+  a.set_weight(3);
+
+  // Clones start out with the weight of their origin.
+  let c = a.clone();
+
+  for n in 0..5 {
+    a.notify(('a', n)).unwrap();
+    b.notify(('b', n)).unwrap();
+  }
+  c.notify(('c', 0)).unwrap();
+
+  let mut order = Vec::new();
+  while let Ok(Some((msg, _rctx))) = server.try_wait() {
+    order.push(msg);
+  }
+  assert_eq!(
+    order,
+    vec![
+      ('a', 0),
+      ('a', 1),
+      ('a', 2),
+      ('b', 0),
+      ('c', 0),
+      ('a', 3),
+      ('a', 4),
+      ('b', 1),
+      ('b', 2),
+      ('b', 3),
+      ('b', 4)
+    ]
+  );
+}
+
+
+#[test]
+fn chatty_client() {
+  let (server, client) = fair_channel_bounded::<u32, u32, ()>(4);
+
+  // One client keeps the queue busy ..
+  let chatty = client.clone();
+  let chatty_thread = thread::spawn(move || {
+    for n in 0..1000 {
+      if chatty.send(n).is_err() {
+        break;
+      }
+    }
+  });
+
+  let server_thread = thread::spawn(move || {
+    while let Some((n, rctx)) = server.wait() {
+      rctx.reply(n).unwrap();
+    }
+  });
+
+  // .. while another one still gets its requests through.
+  for n in 0..100 {
+    assert_eq!(client.send(n).unwrap(), n);
+  }
+
+  chatty_thread.join().unwrap();
+  drop(client);
+  server_thread.join().unwrap();
+}
+
+
+#[test]
+fn async_fair() {
+  let tokrt = tokio::runtime::Runtime::new().unwrap();
+
+  let (server, a) = fair_channel::<(char, u32), (), ()>();
+  let b = a.clone();
+
+  tokrt.block_on(async {
+    a.anotify(('a', 0)).await.unwrap();
+    a.anotify(('a', 1)).await.unwrap();
+    b.anotify(('b', 0)).await.unwrap();
+    drop(a);
+    drop(b);
+
+    let mut order = Vec::new();
+    while let Some((msg, _rctx)) = server.async_wait().await {
+      order.push(msg);
+    }
+    assert_eq!(order, vec![('a', 0), ('b', 0), ('a', 1)]);
+  });
+}
+
+// vim: set ft=rust et sw=2 ts=2 sts=2 cinoptions=2 tw=79 :
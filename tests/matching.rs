@@ -0,0 +1,181 @@
+// Make sure that servers can receive selected messages while leaving the
+// others queued.
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+use ump::{channel, priority_channel, Priority};
+
+#[derive(Clone, Debug, PartialEq)]
+enum Msg {
+  Begin,
+  Put(u32),
+  Commit
+}
+
+/// Wait until a server's predicate has been called on `msg`.  The predicate
+/// is called while the server is waiting for a match, so this means that the
+/// server has looked at `msg` and is still waiting.
+fn wait_seen(seen: &mpsc::Receiver<Msg>, msg: Msg) {
+  while seen.recv().unwrap() != msg {}
+}
+
+#[test]
+fn leaves_others_in_order() {
+  let (server, client) = channel::<Msg, u32, ()>();
+
+  let pending: Vec<_> = vec![Msg::Put(1), Msg::Commit, Msg::Put(2)]
+    .into_iter()
+    .map(|msg| client.post(msg).unwrap())
+    .collect();
+
+  let (msg, rctx) = server
+    .try_wait_matching(|msg| *msg == Msg::Commit)
+    .unwrap()
+    .unwrap();
+  assert_eq!(msg, Msg::Commit);
+  rctx.reply(0).unwrap();
+  let res = server.try_wait_matching(|msg| *msg == Msg::Begin);
+  assert!(matches!(res, Ok(None)));
+
+  let mut order = Vec::new();
+  while let Ok(Some((msg, rctx))) = server.try_wait() {
+    if let Msg::Put(n) = msg {
+      order.push(n);
+      rctx.reply(n).unwrap();
+    }
+  }
+  assert_eq!(order, vec![1, 2]);
+
+  let replies: Vec<u32> =
+    pending.into_iter().map(|w| w.wait().unwrap()).collect();
+  assert_eq!(replies, vec![1, 0, 2]);
+}
+
+
+#[test]
+fn waits_for_match() {
+  let (server, client) = channel::<Msg, (), ()>();
+  let (seen_tx, seen_rx) = mpsc::channel();
+
+  let client_thread = thread::spawn(move || {
+    client.notify(Msg::Begin).unwrap();
+    client.notify(Msg::Put(1)).unwrap();
+    wait_seen(&seen_rx, Msg::Put(1));
+    client.notify(Msg::Put(2)).unwrap();
+    wait_seen(&seen_rx, Msg::Put(2));
+    client.notify(Msg::Commit).unwrap();
+    client.notify(Msg::Put(3)).unwrap();
+  });
+
+  let (msg, _) = server.wait().unwrap();
+  assert_eq!(msg, Msg::Begin);
+
+  // Messages that don't match don't end the wait.
+  let is_commit = |msg: &Msg| {
+    let _ = seen_tx.send(msg.clone());
+    *msg == Msg::Commit
+  };
+  let (msg, _) = server.wait_matching(is_commit).unwrap();
+  assert_eq!(msg, Msg::Commit);
+
+  client_thread.join().unwrap();
+
+  // Once all clients are gone the remaining messages can still be received,
+  // but waiting for messages that will never arrive returns `None`.
+  assert!(server.wait_matching(|msg| *msg == Msg::Begin).is_none());
+  let mut rest = Vec::new();
+  while let Some((msg, _)) = server.wait() {
+    rest.push(msg);
+  }
+  assert_eq!(rest, vec![Msg::Put(1), Msg::Put(2), Msg::Put(3)]);
+}
+
+
+#[test]
+fn closed_server() {
+  let (server, client) = channel::<Msg, (), ()>();
+
+  client.notify(Msg::Put(1)).unwrap();
+  let server2 = server.clone();
+  let (seen_tx, seen_rx) = mpsc::channel();
+  let waiter = thread::spawn(move || {
+    server2.wait_matching(|msg| {
+      let _ = seen_tx.send(msg.clone());
+      *msg == Msg::Commit
+    })
+  });
+
+  wait_seen(&seen_rx, Msg::Put(1));
+  server.close();
+  assert!(waiter.join().unwrap().is_none());
+
+  let (msg, _) = server.wait().unwrap();
+  assert_eq!(msg, Msg::Put(1));
+}
+
+
+#[test]
+fn highest_priority_match() {
+  let (server, client) = priority_channel::<Msg, (), ()>(Priority::new());
+
+  client.notify(Msg::Put(1)).unwrap();
+  client.notify(Msg::Commit).unwrap();
+  let _high = client.post_with_priority(Msg::Put(2), 1).unwrap();
+  let _higher = client.post_with_priority(Msg::Begin, 2).unwrap();
+
+  let (msg, _) = server
+    .try_wait_matching(|msg| matches!(msg, Msg::Put(_)))
+    .unwrap()
+    .unwrap();
+  assert_eq!(msg, Msg::Put(2));
+
+  let mut rest = Vec::new();
+  while let Ok(Some((msg, _))) = server.try_wait() {
+    rest.push(msg);
+  }
+  assert_eq!(rest, vec![Msg::Begin, Msg::Put(1), Msg::Commit]);
+}
+
+
+#[test]
+fn async_matching() {
+  let tokrt = tokio::runtime::Runtime::new().unwrap();
+
+  let (server, client) = channel::<Msg, (), ()>();
+
+  tokrt.block_on(async {
+    let server2 = server.clone();
+    let (seen_tx, mut seen_rx) = tokio::sync::mpsc::unbounded_channel();
+    let waiter = tokio::spawn(async move {
+      let (msg, _) = server2
+        .async_wait_matching(|msg| {
+          let _ = seen_tx.send(msg.clone());
+          *msg == Msg::Commit
+        })
+        .await
+        .unwrap();
+      msg
+    });
+
+    client.anotify(Msg::Put(1)).await.unwrap();
+    while seen_rx.recv().await.unwrap() != Msg::Put(1) {}
+    assert!(!waiter.is_finished());
+    client.anotify(Msg::Commit).await.unwrap();
+    assert_eq!(waiter.await.unwrap(), Msg::Commit);
+
+    let (msg, _) = server.async_wait().await.unwrap();
+    assert_eq!(msg, Msg::Put(1));
+
+    // Dropping a waiting future leaves the queue in a usable state.
+    let fut = server.async_wait_matching(|msg| *msg == Msg::Begin);
+    assert!(tokio::time::timeout(Duration::from_millis(10), fut)
+      .await
+      .is_err());
+    client.anotify(Msg::Begin).await.unwrap();
+    let (msg, _) = server.async_wait().await.unwrap();
+    assert_eq!(msg, Msg::Begin);
+  });
+}
+
+// vim: set ft=rust et sw=2 ts=2 sts=2 cinoptions=2 tw=79 :
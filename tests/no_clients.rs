@@ -0,0 +1,99 @@
+// Make sure that servers stop waiting once all clients are gone.
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use ump::{channel, Closed};
+
+#[test]
+fn sync_wait_ends() {
+  let (server, client) = channel::<u32, u32, ()>();
+  let client2 = client.clone();
+  let (waiting_tx, waiting_rx) = mpsc::channel();
+
+  let server_thread = thread::spawn(move || {
+    let mut count = 0;
+    waiting_tx.send(()).unwrap();
+    while let Some((n, rctx)) = server.wait() {
+      rctx.reply(n * 2).unwrap();
+      count += 1;
+      waiting_tx.send(()).unwrap();
+    }
+    assert!(!server.has_clients());
+    count
+  });
+
+  waiting_rx.recv().unwrap();
+  assert_eq!(client.send(1).unwrap(), 2);
+  drop(client);
+
+  // A single remaining clone keeps the server alive.
+  waiting_rx.recv().unwrap();
+  assert_eq!(client2.send(2).unwrap(), 4);
+  drop(client2);
+
+  assert_eq!(server_thread.join().unwrap(), 2);
+}
+
+
+#[test]
+fn queued_messages_delivered_first() {
+  let (server, client) = channel::<u32, u32, ()>();
+
+  let pending = client.post(1).unwrap();
+  drop(client);
+  assert!(!server.has_clients());
+
+  let (n, rctx) = server.wait().unwrap();
+  rctx.reply(n * 2).unwrap();
+  assert_eq!(pending.wait().unwrap(), 2);
+
+  assert!(server.wait().is_none());
+  assert!(matches!(server.try_wait(), Err(Closed)));
+  assert!(matches!(server.wait_timeout(Duration::ZERO), Err(Closed)));
+}
+
+
+#[test]
+fn wait_timeout_ends_early() {
+  let (server, client) = channel::<u32, u32, ()>();
+  let (waiting_tx, waiting_rx) = mpsc::channel();
+
+  let server_thread = thread::spawn(move || {
+    let start = Instant::now();
+    waiting_tx.send(()).unwrap();
+    let res = server.wait_timeout(Duration::from_secs(60));
+    assert!(matches!(res, Err(Closed)));
+    start.elapsed()
+  });
+
+  waiting_rx.recv().unwrap();
+  drop(client);
+
+  assert!(server_thread.join().unwrap() < Duration::from_secs(60));
+}
+
+
+#[test]
+fn async_wait_ends() {
+  let tokrt = tokio::runtime::Runtime::new().unwrap();
+
+  let (server, client) = channel::<u32, u32, ()>();
+
+  let server_task = tokrt.spawn(async move {
+    let mut count = 0;
+    while let Some((n, rctx)) = server.async_wait().await {
+      rctx.reply(n * 2).unwrap();
+      count += 1;
+    }
+    count
+  });
+
+  tokrt.block_on(async {
+    assert_eq!(client.asend(1).await.unwrap(), 2);
+    drop(client);
+    assert_eq!(server_task.await.unwrap(), 1);
+  });
+}
+
+// vim: set ft=rust et sw=2 ts=2 sts=2 cinoptions=2 tw=79 :
@@ -4,12 +4,13 @@ use std::thread;
 use ump::{channel, Error};
 
 #[test]
+#[allow(clippy::useless_conversion)]
 fn sync_expect_noreply() {
   let (server, client) = channel::<String, String, ()>();
 
   let server_thread = thread::spawn(move || {
     // Wait for data to arrive from a client
-    let (_, rctx) = server.wait();
+    let (_, rctx) = server.wait().unwrap();
 
     // Don't do this.
     drop(rctx);
@@ -38,7 +39,7 @@ fn async_expect_noreply() {
 
   let server_thread = thread::spawn(move || {
     // Wait for data to arrive from a client
-    let (_, rctx) = server.wait();
+    let (_, rctx) = server.wait().unwrap();
 
     // Don't do this.
     drop(rctx);
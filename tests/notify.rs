@@ -0,0 +1,104 @@
+// Make sure that one-way notifications can be mixed with requests.
+use std::thread;
+
+use ump::{channel, channel_bounded, Error, Incoming, ReplyError};
+
+#[test]
+fn ordered_with_requests() {
+  let (server, client) = channel::<u32, Vec<u32>, ()>();
+
+  let server_thread = thread::spawn(move || {
+    let mut seen = Vec::new();
+    while let Some(incoming) = server.wait_incoming() {
+      match incoming {
+        Incoming::Notify(n) => seen.push(n),
+        Incoming::Request(n, rctx) => {
+          seen.push(n);
+          rctx.reply(seen.clone()).unwrap();
+        }
+        Incoming::Stream(..) => panic!("Unexpected stream request")
+      }
+    }
+    seen
+  });
+
+  client.notify(1).unwrap();
+  client.notify(2).unwrap();
+  assert_eq!(client.send(3).unwrap(), vec![1, 2, 3]);
+  client.notify(4).unwrap();
+  drop(client);
+
+  assert_eq!(server_thread.join().unwrap(), vec![1, 2, 3, 4]);
+}
+
+
+#[test]
+fn plain_wait_returns_reply() {
+  let (server, client) = channel::<u32, u32, ()>();
+
+  client.notify(1).unwrap();
+  let pending = client.post(2).unwrap();
+
+  // Nobody is waiting for a reply to the notification.
+  let (n, rctx) = server.wait().unwrap();
+  assert_eq!(n, 1);
+  assert!(rctx.is_cancelled());
+  match rctx.reply(10) {
+    Err(ReplyError::Cancelled(reply)) => assert_eq!(reply, 10),
+    res => panic!("Unexpected result {:?}", res)
+  }
+
+  let (n, rctx) = server.wait().unwrap();
+  assert_eq!(n, 2);
+  rctx.reply(20).unwrap();
+
+  assert_eq!(pending.wait().unwrap(), 20);
+}
+
+
+#[test]
+fn async_notify() {
+  let tokrt = tokio::runtime::Runtime::new().unwrap();
+
+  let (server, client) = channel_bounded::<u32, u32, ()>(1);
+
+  let server_task = tokrt.spawn(async move {
+    let mut sum = 0;
+    while let Some(incoming) = server.async_wait_incoming().await {
+      match incoming {
+        Incoming::Notify(n) => sum += n,
+        Incoming::Request(_, rctx) => rctx.reply(sum).unwrap(),
+        Incoming::Stream(..) => panic!("Unexpected stream request")
+      }
+    }
+  });
+
+  tokrt.block_on(async {
+    for n in 1..=10 {
+      client.anotify(n).await.unwrap();
+    }
+    assert_eq!(client.asend(0).await.unwrap(), 55);
+    drop(client);
+    server_task.await.unwrap();
+  });
+}
+
+
+#[test]
+fn notify_rejected() {
+  let (server, client) = channel::<u32, u32, ()>();
+
+  server.close();
+  match client.notify(1) {
+    Err(Error::ServerClosed(n)) => assert_eq!(n, 1),
+    res => panic!("Unexpected result {:?}", res)
+  }
+
+  drop(server);
+  match client.notify(2) {
+    Err(Error::ServerDisappeared(n)) => assert_eq!(n, 2),
+    res => panic!("Unexpected result {:?}", res)
+  }
+}
+
+// vim: set ft=rust et sw=2 ts=2 sts=2 cinoptions=2 tw=79 :
@@ -0,0 +1,73 @@
+// Make sure that server panics are reported to clients.
+use std::panic;
+use std::thread;
+
+use ump::{channel, Error};
+
+#[test]
+fn run_catches_panic() {
+  let (server, client) = channel::<u32, u32, ()>();
+
+  let server_thread = thread::spawn(move || {
+    // A panicking handler must not take down the server.
+    for _ in 0..2 {
+      let (data, rctx) = server.wait().unwrap();
+      let _ = rctx.run(move || {
+        if data == 0 {
+          panic!("Bad input {}", data);
+        }
+        Ok(data * 2)
+      });
+    }
+  });
+
+  match client.send(0) {
+    Err(Error::ServerPanicked(msg)) => assert_eq!(msg, "Bad input 0"),
+    res => panic!("Unexpected result {:?}", res)
+  }
+  assert_eq!(client.send(21).unwrap(), 42);
+
+  server_thread.join().unwrap();
+}
+
+
+#[test]
+fn panic_while_holding_rctx() {
+  let (server, client) = channel::<String, String, ()>();
+
+  let server_thread = thread::spawn(move || {
+    let (_data, _rctx) = server.wait().unwrap();
+    panic!("Handler failed");
+  });
+
+  match client.send(String::from("Client")) {
+    Err(Error::ServerPanicked(_)) => {}
+    res => panic!("Unexpected result {:?}", res)
+  }
+
+  assert!(server_thread.join().is_err());
+}
+
+
+#[test]
+fn run_resume_unwind() {
+  let (server, client) = channel::<String, String, ()>();
+
+  let server_thread = thread::spawn(move || {
+    let (_data, rctx) = server.wait().unwrap();
+    let res = rctx.run(|| panic!("Resumed"));
+    if let Err(payload) = res {
+      panic::resume_unwind(payload);
+    }
+  });
+
+  match client.send(String::from("Client")) {
+    Err(Error::ServerPanicked(msg)) => assert_eq!(msg, "Resumed"),
+    res => panic!("Unexpected result {:?}", res)
+  }
+
+  let payload = server_thread.join().unwrap_err();
+  assert_eq!(payload.downcast_ref::<&str>(), Some(&"Resumed"));
+}
+
+// vim: set ft=rust et sw=2 ts=2 sts=2 cinoptions=2 tw=79 :
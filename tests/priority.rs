@@ -0,0 +1,142 @@
+// Make sure that priority channels hand out messages in priority order.
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+use ump::{channel, priority_channel, priority_channel_bounded, Priority};
+
+#[test]
+fn highest_priority_first() {
+  let (server, client) = priority_channel::<u32, u32, ()>(Priority::new());
+
+  let mut pending = Vec::new();
+  for (n, prio) in [(1, 0), (2, -5), (3, 10), (4, 0), (5, 10)] {
+    pending.push(client.post_with_priority(n, prio).unwrap());
+  }
+  pending.push(client.post(6).unwrap());
+
+  let mut order = Vec::new();
+  while let Ok(Some((n, rctx))) = server.try_wait() {
+    order.push(n);
+    rctx.reply(n).unwrap();
+  }
+
+  // Equal priorities are received in the order they were sent.
+  assert_eq!(order, vec![3, 5, 1, 4, 6, 2]);
+
+  for (wrply, n) in pending.into_iter().zip(1..) {
+    assert_eq!(wrply.wait().unwrap(), n);
+  }
+}
+
+
+#[test]
+fn fifo_ignores_priority() {
+  let (server, client) = channel::<u32, u32, ()>();
+
+  let mut pending = Vec::new();
+  for (n, prio) in [(1, 0), (2, 10), (3, -10)] {
+    pending.push(client.post_with_priority(n, prio).unwrap());
+  }
+
+  let mut order = Vec::new();
+  while let Ok(Some((n, rctx))) = server.try_wait() {
+    order.push(n);
+    rctx.reply(n).unwrap();
+  }
+  assert_eq!(order, vec![1, 2, 3]);
+}
+
+
+#[test]
+fn aging_prevents_starvation() {
+  let policy = Priority::new().aging(Duration::from_millis(10));
+  let (server, client) = priority_channel::<&str, (), ()>(policy);
+
+  let low = client.post_with_priority("low", 0).unwrap();
+  thread::sleep(Duration::from_millis(100));
+
+  // "low" has been waiting long enough to outrank a newly sent priority 5
+  // message, but would have to wait for hours to outrank a priority 1000000
+  // one.
+  let medium = client.post_with_priority("medium", 5).unwrap();
+  let high = client.post_with_priority("high", 1_000_000).unwrap();
+
+  let mut order = Vec::new();
+  while let Ok(Some((msg, rctx))) = server.try_wait() {
+    order.push(msg);
+    rctx.reply(()).unwrap();
+  }
+  assert_eq!(order, vec!["high", "low", "medium"]);
+
+  low.wait().unwrap();
+  medium.wait().unwrap();
+  high.wait().unwrap();
+}
+
+
+#[test]
+fn bounded_blocking_clients() {
+  let (server, client) =
+    priority_channel_bounded::<i32, i32, ()>(Priority::new(), 2);
+
+  // Fill the queue, and let a high priority sender wait for space.
+  let first = client.post(1).unwrap();
+  let second = client.post(2).unwrap();
+
+  let client2 = client.clone();
+  let (queued_tx, queued_rx) = mpsc::channel();
+  let urgent = thread::spawn(move || {
+    let wrply = client2.post_with_priority(100, 100).unwrap();
+    queued_tx.send(()).unwrap();
+    wrply.wait().unwrap()
+  });
+  drop(client);
+
+  // Receiving a message makes room for the urgent one, which then goes to the
+  // front of the queue.
+  let (n, rctx) = server.wait().unwrap();
+  assert_eq!(n, 1);
+  rctx.reply(n).unwrap();
+  queued_rx.recv().unwrap();
+
+  let mut order = Vec::new();
+  while let Some((n, rctx)) = server.wait() {
+    order.push(n);
+    rctx.reply(n).unwrap();
+  }
+  assert_eq!(order, vec![100, 2]);
+
+  assert_eq!(urgent.join().unwrap(), 100);
+  assert_eq!(first.wait().unwrap(), 1);
+  assert_eq!(second.wait().unwrap(), 2);
+}
+
+
+#[test]
+fn async_priority() {
+  let tokrt = tokio::runtime::Runtime::new().unwrap();
+
+  let (server, client) = priority_channel::<u32, u32, ()>(Priority::new());
+
+  tokrt.block_on(async {
+    let low = client.post(1).unwrap();
+
+    // The message is queued the first time the future is polled.
+    let high = client.asend_with_priority(2, 1);
+    tokio::pin!(high);
+    assert!(futures::poll!(&mut high).is_pending());
+
+    let (n, rctx) = server.async_wait().await.unwrap();
+    assert_eq!(n, 2);
+    rctx.reply(n).unwrap();
+    assert_eq!(high.await.unwrap(), 2);
+
+    let (n, rctx) = server.async_wait().await.unwrap();
+    assert_eq!(n, 1);
+    rctx.reply(n).unwrap();
+    assert_eq!(low.await.unwrap(), 1);
+  });
+}
+
+// vim: set ft=rust et sw=2 ts=2 sts=2 cinoptions=2 tw=79 :
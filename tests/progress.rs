@@ -0,0 +1,111 @@
+// Make sure that servers can report progress to waiting clients.
+use std::sync::mpsc;
+use std::thread;
+
+use ump::{progress_channel, progress_channel_bounded, ReplyError};
+
+#[test]
+fn callback_receives_updates() {
+  let (server, client) = progress_channel::<u32, u32, (), u32>();
+
+  let server_thread = thread::spawn(move || {
+    while let Some((n, rctx)) = server.wait() {
+      for i in 0..n {
+        rctx.progress(i).unwrap();
+      }
+      rctx.reply(n).unwrap();
+    }
+  });
+
+  let mut updates = Vec::new();
+  let reply = client.send_with_progress(5, |p| updates.push(p)).unwrap();
+  assert_eq!(reply, 5);
+  assert_eq!(updates, vec![0, 1, 2, 3, 4]);
+
+  // Clients that don't care about progress just get the reply.
+  assert_eq!(client.send(3).unwrap(), 3);
+
+  drop(client);
+  server_thread.join().unwrap();
+}
+
+
+#[test]
+fn poll_deferred_handle() {
+  let (server, client) = progress_channel::<(), (), (), String>();
+  let (tx, rx) = mpsc::channel();
+
+  let mut wrply = client.post(()).unwrap();
+  assert!(wrply.try_progress().is_none());
+
+  let server_thread = thread::spawn(move || {
+    let (_, rctx) = server.wait().unwrap();
+    rctx.progress(String::from("Working")).unwrap();
+    rx.recv().unwrap();
+    rctx.reply(()).unwrap();
+  });
+
+  assert_eq!(wrply.wait_progress().unwrap(), "Working");
+  assert!(wrply.try_progress().is_none());
+  let mut wrply = wrply.try_get().unwrap_err();
+  tx.send(()).unwrap();
+
+  assert!(wrply.wait_progress().is_none());
+  wrply.wait().unwrap();
+
+  server_thread.join().unwrap();
+}
+
+
+#[test]
+fn progress_after_cancel() {
+  let (server, client) = progress_channel::<(), (), (), u32>();
+
+  let wrply = client.post(()).unwrap();
+  let (_, rctx) = server.wait().unwrap();
+  drop(wrply);
+
+  match rctx.progress(50) {
+    Err(ReplyError::Cancelled(p)) => assert_eq!(p, 50),
+    res => panic!("Unexpected result {:?}", res)
+  }
+}
+
+
+#[test]
+fn async_progress() {
+  let tokrt = tokio::runtime::Runtime::new().unwrap();
+
+  let (server, client) = progress_channel_bounded::<u32, u32, (), u32>(1);
+
+  let server_task = tokrt.spawn(async move {
+    while let Some((n, rctx)) = server.async_wait().await {
+      for i in 0..n {
+        rctx.progress(i * 10).unwrap();
+        tokio::task::yield_now().await;
+      }
+      rctx.reply(n).unwrap();
+    }
+  });
+
+  tokrt.block_on(async {
+    let mut updates = Vec::new();
+    let reply = client
+      .asend_with_progress(4, |p| updates.push(p))
+      .await
+      .unwrap();
+    assert_eq!(reply, 4);
+    assert_eq!(updates, vec![0, 10, 20, 30]);
+
+    let mut wrply = client.post(2).unwrap();
+    assert_eq!(wrply.async_progress().await, Some(0));
+    assert_eq!(wrply.async_progress().await, Some(10));
+    assert_eq!(wrply.async_progress().await, None);
+    assert_eq!(wrply.await.unwrap(), 2);
+
+    drop(client);
+    server_task.await.unwrap();
+  });
+}
+
+// vim: set ft=rust et sw=2 ts=2 sts=2 cinoptions=2 tw=79 :
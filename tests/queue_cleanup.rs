@@ -5,6 +5,7 @@ use std::{thread, time};
 use ump::{channel, Error};
 
 #[test]
+#[allow(clippy::useless_conversion)]
 fn sync_expect_server_death() {
   let (server, client) = channel::<String, String, ()>();
 
@@ -18,8 +19,9 @@ fn sync_expect_server_death() {
   let msg = String::from("Client");
   let reply = client.send(String::from(msg));
   match reply {
-    Err(Error::ServerDisappeared) => {
-      // This is the expected error
+    Err(Error::ServerDisappeared(msg)) => {
+      // This is the expected error; the message is handed back
+      assert_eq!(msg, "Client");
     }
     _ => {
       panic!("Unexpected return value");
@@ -48,8 +50,9 @@ fn async_expect_server_death() {
     let reply = client.asend(msg).await;
     //let reply = client.send(msg);
     match reply {
-      Err(Error::ServerDisappeared) => {
-        // This is the expected error
+      Err(Error::ServerDisappeared(msg)) => {
+        // This is the expected error; the message is handed back
+        assert_eq!(msg, "Client");
       }
       _ => {
         panic!("Unexpected return value");
@@ -60,4 +63,24 @@ fn async_expect_server_death() {
   server_thread.join().unwrap();
 }
 
+
+#[test]
+fn expect_server_already_gone() {
+  let (server, client) = channel::<String, String, ()>();
+
+  drop(server);
+
+  let msg = String::from("Client");
+  let reply = client.send(msg);
+  match reply {
+    Err(Error::ServerDisappeared(msg)) => {
+      // This is the expected error; the message is handed back
+      assert_eq!(msg, "Client");
+    }
+    _ => {
+      panic!("Unexpected return value");
+    }
+  }
+}
+
 // vim: set ft=rust et sw=2 ts=2 sts=2 cinoptions=2 tw=79 :
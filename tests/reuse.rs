@@ -0,0 +1,151 @@
+// Make sure that clients which reuse their reply slots get the same results
+// as clients that don't, including in the corner cases where the server is
+// still holding on to the slot of an earlier request.
+use std::sync::{mpsc, Arc};
+use std::thread;
+use std::time::Duration;
+
+use ump::{channel, Error, ReplyError, Server};
+
+fn echo_server(server: Server<u32, u32, ()>) -> thread::JoinHandle<()> {
+  thread::spawn(move || {
+    while let Some((n, rctx)) = server.wait() {
+      rctx.reply(n).unwrap();
+    }
+  })
+}
+
+
+#[test]
+fn late_reply_is_not_delivered_to_next_request() {
+  let (server, mut client) = channel::<String, String, ()>();
+  client.set_reuse_slot(true);
+  let (tx, rx) = mpsc::channel();
+
+  let server_thread = thread::spawn(move || {
+    let (_, late_rctx) = server.wait().unwrap();
+
+    // Handle the next request while still holding on to the reply context
+    // of the one that timed out.
+    rx.recv().unwrap();
+    let (data, rctx) = server.wait().unwrap();
+    rctx.reply(format!("Hello, {}!", data)).unwrap();
+
+    match late_rctx.reply(String::from("Too late")) {
+      Err(ReplyError::Cancelled(reply)) => assert_eq!(reply, "Too late"),
+      _ => panic!("Late reply was not returned")
+    }
+
+    while let Some((data, rctx)) = server.wait() {
+      rctx.reply(format!("Hello again, {}!", data)).unwrap();
+    }
+  });
+
+  let reply =
+    client.send_timeout(String::from("First"), Duration::from_millis(50));
+  assert!(matches!(reply, Err(Error::Timeout)));
+  tx.send(()).unwrap();
+
+  let reply = client.send(String::from("Second")).unwrap();
+  assert_eq!(reply, "Hello, Second!");
+
+  for _ in 0..16 {
+    let reply = client.send(String::from("Third")).unwrap();
+    assert_eq!(reply, "Hello again, Third!");
+  }
+
+  drop(client);
+  server_thread.join().unwrap();
+}
+
+
+#[test]
+fn send_after_noreply() {
+  let (server, mut client) = channel::<String, String, ()>();
+  client.set_reuse_slot(true);
+
+  let server_thread = thread::spawn(move || {
+    let (_, rctx) = server.wait().unwrap();
+    drop(rctx);
+
+    let (data, rctx) = server.wait().unwrap();
+    rctx.reply(format!("Hello, {}!", data)).unwrap();
+  });
+
+  let reply = client.send(String::from("Client"));
+  assert!(matches!(reply, Err(Error::NoReply)));
+
+  let reply = client.send(String::from("Client")).unwrap();
+  assert_eq!(reply, "Hello, Client!");
+
+  server_thread.join().unwrap();
+}
+
+
+#[test]
+fn send_after_cancelled_asend() {
+  let tokrt = tokio::runtime::Runtime::new().unwrap();
+
+  let (server, mut client) = channel::<u32, u32, ()>();
+  client.set_reuse_slot(true);
+  let (tx, rx) = mpsc::channel();
+
+  let server_thread = thread::spawn(move || {
+    // Hold on to the first request until the client has given up on it.
+    let (n, rctx) = server.wait().unwrap();
+    rx.recv().unwrap();
+    assert!(rctx.reply(n).is_err());
+
+    while let Some((n, rctx)) = server.wait() {
+      rctx.reply(n * 2).unwrap();
+    }
+  });
+
+  tokrt.block_on(async {
+    let reply =
+      tokio::time::timeout(Duration::from_millis(50), client.asend(1)).await;
+    assert!(reply.is_err());
+    tx.send(()).unwrap();
+
+    for n in 2..18 {
+      assert_eq!(client.asend(n).await.unwrap(), n * 2);
+    }
+  });
+
+  drop(client);
+  server_thread.join().unwrap();
+}
+
+
+#[test]
+fn shared_between_threads() {
+  let nthreads = 4;
+  let niterations = 1000;
+
+  let (server, mut client) = channel::<u32, u32, ()>();
+  client.set_reuse_slot(true);
+  let server_thread = echo_server(server);
+
+  // Requests sent concurrently through the same client must each get their
+  // own reply.
+  let client = Arc::new(client);
+  let threads: Vec<_> = (0..nthreads)
+    .map(|t| {
+      let client = Arc::clone(&client);
+      thread::spawn(move || {
+        for n in 0..niterations {
+          let n = t * niterations + n;
+          assert_eq!(client.send(n).unwrap(), n);
+        }
+      })
+    })
+    .collect();
+  for t in threads {
+    t.join().unwrap();
+  }
+
+  drop(client);
+  server_thread.join().unwrap();
+}
+
+// vim: set ft=rust et sw=2 ts=2 sts=2 cinoptions=2 tw=79 :
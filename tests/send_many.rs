@@ -0,0 +1,174 @@
+// Make sure that batches of messages are queued together, and that each
+// message in the batch gets its own reply.
+use std::thread;
+use std::time::Duration;
+
+use ump::{channel, channel_bounded, Error};
+
+#[test]
+fn replies_in_order() {
+  let (server, client) = channel::<u32, u32, String>();
+
+  let server_thread = thread::spawn(move || {
+    while let Some((n, rctx)) = server.wait() {
+      if n == 3 {
+        rctx.fail(String::from("Three")).unwrap();
+      } else {
+        rctx.reply(n * 2).unwrap();
+      }
+    }
+  });
+
+  let replies = client.send_many(vec![1, 2, 3, 4]);
+  assert_eq!(replies.len(), 4);
+  assert_eq!(replies[0].as_ref().unwrap(), &2);
+  assert_eq!(replies[1].as_ref().unwrap(), &4);
+  match &replies[2] {
+    Err(Error::App(err)) => assert_eq!(err, "Three"),
+    res => panic!("Unexpected result {:?}", res)
+  }
+  assert_eq!(replies[3].as_ref().unwrap(), &8);
+
+  assert!(client.send_many(Vec::new()).is_empty());
+
+  drop(client);
+  server_thread.join().unwrap();
+}
+
+
+#[test]
+fn queued_at_once() {
+  let (server, client) = channel::<u32, u32, ()>();
+
+  // The server doesn't reply to anything until it has received the whole
+  // batch, which it finds in the queue as soon as the first message arrives.
+  let server_thread = thread::spawn(move || {
+    let mut batch = vec![server.wait().unwrap()];
+    while let Ok(Some(pair)) = server.try_wait() {
+      batch.push(pair);
+    }
+    assert_eq!(batch.len(), 8);
+    for (n, rctx) in batch {
+      rctx.reply(n + 1).unwrap();
+    }
+  });
+
+  let replies: Result<Vec<u32>, _> =
+    client.send_many(0..8).into_iter().collect();
+  assert_eq!(replies.unwrap(), (1..9).collect::<Vec<u32>>());
+
+  server_thread.join().unwrap();
+}
+
+
+#[test]
+fn bounded_queue() {
+  let (server, client) = channel_bounded::<u32, u32, ()>(2);
+
+  let server_thread = thread::spawn(move || {
+    while let Some((n, rctx)) = server.wait() {
+      rctx.reply(n).unwrap();
+    }
+  });
+
+  let replies: Result<Vec<u32>, _> =
+    client.send_many(0..100).into_iter().collect();
+  assert_eq!(replies.unwrap(), (0..100).collect::<Vec<u32>>());
+
+  drop(client);
+  server_thread.join().unwrap();
+}
+
+
+#[test]
+fn closed_while_queueing() {
+  let (server, client) = channel_bounded::<u32, u32, ()>(1);
+
+  let client_thread = thread::spawn(move || client.send_many(vec![1, 2, 3]));
+
+  // Close once the first message is in the queue.  The rest are turned away,
+  // whether or not the client has gotten around to waiting for room for them
+  // yet.
+  while server.was_empty() {
+    thread::yield_now();
+  }
+  server.close();
+
+  // The message that made it into the queue is still handled.
+  let (n, rctx) = server.wait().unwrap();
+  rctx.reply(n * 2).unwrap();
+  assert!(server.wait().is_none());
+
+  let replies = client_thread.join().unwrap();
+  assert_eq!(replies.len(), 3);
+  assert_eq!(replies[0].as_ref().unwrap(), &2);
+  match &replies[1] {
+    Err(Error::ServerClosed(n)) => assert_eq!(*n, 2),
+    res => panic!("Unexpected result {:?}", res)
+  }
+  match &replies[2] {
+    Err(Error::ServerClosed(n)) => assert_eq!(*n, 3),
+    res => panic!("Unexpected result {:?}", res)
+  }
+}
+
+
+#[test]
+fn server_disappeared() {
+  let (server, client) = channel::<u32, u32, ()>();
+  drop(server);
+
+  let replies = client.send_many(vec![1, 2]);
+  assert_eq!(replies.len(), 2);
+  for (reply, expected) in replies.into_iter().zip(vec![1, 2]) {
+    match reply {
+      Err(Error::ServerDisappeared(n)) => assert_eq!(n, expected),
+      res => panic!("Unexpected result {:?}", res)
+    }
+  }
+}
+
+
+#[test]
+fn async_send_many() {
+  let tokrt = tokio::runtime::Runtime::new().unwrap();
+
+  let (server, client) = channel_bounded::<u32, u32, ()>(4);
+
+  let server_thread = thread::spawn(move || {
+    while let Some((n, rctx)) = server.wait() {
+      rctx.reply(n * n).unwrap();
+    }
+  });
+
+  tokrt.block_on(async {
+    let replies: Result<Vec<u32>, _> =
+      client.asend_many(0..32).await.into_iter().collect();
+    assert_eq!(replies.unwrap(), (0..32).map(|n| n * n).collect::<Vec<_>>());
+  });
+
+  drop(client);
+  server_thread.join().unwrap();
+}
+
+
+#[test]
+fn async_dropped_cancels_requests() {
+  let tokrt = tokio::runtime::Runtime::new().unwrap();
+
+  let (server, client) = channel::<u32, u32, ()>();
+
+  tokrt.block_on(async {
+    let res = tokio::time::timeout(
+      Duration::from_millis(50),
+      client.asend_many(vec![1, 2, 3])
+    )
+    .await;
+    assert!(res.is_err());
+  });
+
+  // All of the requests were cancelled, so the server skips them.
+  assert!(matches!(server.try_wait(), Ok(None)));
+}
+
+// vim: set ft=rust et sw=2 ts=2 sts=2 cinoptions=2 tw=79 :
@@ -0,0 +1,186 @@
+// Make sure that Server::serve() replies using the handler's results, and
+// that it returns once the clients are gone or the server is shut down.
+use std::future::Future;
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+use ump::{channel, AsyncHandler, Error, Handler};
+
+/// Halves even numbers, and fails odd ones.
+struct Evens;
+
+impl Handler<u32, u32, String> for Evens {
+  fn handle(&mut self, n: u32) -> Result<u32, String> {
+    match n % 2 {
+      0 => Ok(n / 2),
+      _ => Err(format!("{} is odd", n))
+    }
+  }
+}
+
+impl AsyncHandler<u32, u32, String> for Evens {
+  fn handle(
+    &mut self,
+    n: u32
+  ) -> impl Future<Output = Result<u32, String>> + Send {
+    let res = Handler::handle(self, n);
+    async move {
+      tokio::task::yield_now().await;
+      res
+    }
+  }
+}
+
+
+#[test]
+fn serve_until_no_clients() {
+  let (server, client) = channel::<u32, u32, String>();
+
+  let server_thread = thread::spawn(move || server.serve(Evens));
+
+  assert_eq!(client.send(4).unwrap(), 2);
+  match client.send(3) {
+    Err(Error::App(err)) => assert_eq!(err, "3 is odd"),
+    res => panic!("Unexpected result {:?}", res)
+  }
+
+  // The server keeps going as long as there's a client.
+  let client_clone = client.clone();
+  drop(client);
+  assert_eq!(client_clone.send(8).unwrap(), 4);
+  drop(client_clone);
+
+  server_thread.join().unwrap();
+}
+
+
+#[test]
+fn serve_closure() {
+  let (server, client) = channel::<String, usize, ()>();
+
+  let mut total = 0;
+  let server_thread = thread::spawn(move || {
+    server.serve(|msg: String| {
+      total += msg.len();
+      Ok(total)
+    })
+  });
+
+  assert_eq!(client.send(String::from("abc")).unwrap(), 3);
+  assert_eq!(client.send(String::from("de")).unwrap(), 5);
+  drop(client);
+
+  server_thread.join().unwrap();
+}
+
+
+#[test]
+fn shutdown_handles_queued_messages() {
+  let (server, client) = channel::<u32, u32, String>();
+  let shutdown = server.shutdown_token();
+
+  let first = client.post(2).unwrap();
+  let second = client.post(6).unwrap();
+  shutdown.shutdown();
+
+  // Returns right away, even though there's a client left, once the queued
+  // messages have been handled.
+  server.serve(Evens);
+  assert_eq!(first.wait().unwrap(), 1);
+  assert_eq!(second.wait().unwrap(), 3);
+
+  match client.send(4) {
+    Err(Error::ServerClosed(n)) => assert_eq!(n, 4),
+    res => panic!("Unexpected result {:?}", res)
+  }
+}
+
+
+#[test]
+fn shutdown_from_other_thread() {
+  let (server, client) = channel::<u32, u32, String>();
+  let shutdown = server.shutdown_token();
+
+  let (handled_tx, handled_rx) = mpsc::channel();
+  let server_thread = thread::spawn(move || {
+    server.serve(|n: u32| {
+      handled_tx.send(n).unwrap();
+      Handler::handle(&mut Evens, n)
+    })
+  });
+  assert_eq!(client.send(2).unwrap(), 1);
+
+  // Shut down once the server is running its handler.
+  assert_eq!(handled_rx.recv().unwrap(), 2);
+  shutdown.clone().shutdown();
+
+  server_thread.join().unwrap();
+  assert!(client.send(2).is_err());
+}
+
+
+#[test]
+fn shutdown_after_server_dropped() {
+  let (server, client) = channel::<u32, u32, String>();
+  let shutdown = server.shutdown_token();
+  drop(server);
+
+  // The token doesn't keep the server alive, and has nothing left to do.
+  shutdown.shutdown();
+  match client.send(2) {
+    Err(Error::ServerDisappeared(n)) => assert_eq!(n, 2),
+    res => panic!("Unexpected result {:?}", res)
+  }
+}
+
+
+#[test]
+fn async_serve() {
+  let tokrt = tokio::runtime::Runtime::new().unwrap();
+
+  let (server, client) = channel::<u32, u32, String>();
+  let shutdown = server.shutdown_token();
+
+  tokrt.block_on(async {
+    let server_task =
+      tokio::spawn(async move { server.async_serve(Evens).await });
+
+    assert_eq!(client.asend(10).await.unwrap(), 5);
+    match client.asend(5).await {
+      Err(Error::App(err)) => assert_eq!(err, "5 is odd"),
+      res => panic!("Unexpected result {:?}", res)
+    }
+
+    shutdown.shutdown();
+    server_task.await.unwrap();
+  });
+}
+
+
+#[test]
+fn async_serve_closure() {
+  let tokrt = tokio::runtime::Runtime::new().unwrap();
+
+  let (server, client) = channel::<u32, u32, ()>();
+
+  tokrt.block_on(async {
+    let server_task = tokio::spawn(async move {
+      server
+        .async_serve(|n: u32| async move {
+          tokio::time::sleep(Duration::from_millis(1)).await;
+          Ok(n + 1)
+        })
+        .await
+    });
+
+    for n in 0..8 {
+      assert_eq!(client.asend(n).await.unwrap(), n + 1);
+    }
+    drop(client);
+
+    server_task.await.unwrap();
+  });
+}
+
+// vim: set ft=rust et sw=2 ts=2 sts=2 cinoptions=2 tw=79 :
@@ -0,0 +1,69 @@
+// Make sure that the non-blocking and time-bounded server wait calls work.
+use std::thread;
+use std::time::{Duration, Instant};
+
+use ump::{channel, Closed};
+
+#[test]
+fn try_wait() {
+  let (server, client) = channel::<u32, u32, ()>();
+
+  assert!(matches!(server.try_wait(), Ok(None)));
+
+  let client_thread = thread::spawn(move || client.send(21).unwrap());
+
+  // Interleave polling for messages with "other work"
+  let (n, rctx) = loop {
+    if let Some(req) = server.try_wait().unwrap() {
+      break req;
+    }
+    thread::sleep(Duration::from_millis(1));
+  };
+  rctx.reply(n * 2).unwrap();
+
+  assert_eq!(client_thread.join().unwrap(), 42);
+}
+
+
+#[test]
+fn wait_timeout() {
+  let (server, client) = channel::<u32, u32, ()>();
+
+  let start = Instant::now();
+  let res = server.wait_timeout(Duration::from_millis(50));
+  assert!(matches!(res, Ok(None)));
+  assert!(start.elapsed() >= Duration::from_millis(50));
+
+  let client_thread = thread::spawn(move || client.send(21).unwrap());
+
+  let (n, rctx) = server
+    .wait_timeout(Duration::from_secs(10))
+    .unwrap()
+    .unwrap();
+  rctx.reply(n * 2).unwrap();
+
+  assert_eq!(client_thread.join().unwrap(), 42);
+}
+
+
+#[test]
+fn closed_told_apart_from_timeout() {
+  let (server, client) = channel::<u32, u32, ()>();
+
+  client.notify(1).unwrap();
+  server.close();
+
+  // Queued messages are still received, but nothing matching will arrive.
+  let res = server.try_wait_matching(|n| *n == 2);
+  assert!(matches!(res, Err(Closed)));
+  let deadline = Instant::now() + Duration::from_secs(60);
+  let (n, _) = server.wait_deadline(deadline).unwrap().unwrap();
+  assert_eq!(n, 1);
+
+  // Returns right away rather than waiting for the deadline.
+  assert!(matches!(server.wait_deadline(deadline), Err(Closed)));
+  assert!(matches!(server.try_wait_incoming(), Err(Closed)));
+  assert!(Instant::now() < deadline);
+}
+
+// vim: set ft=rust et sw=2 ts=2 sts=2 cinoptions=2 tw=79 :
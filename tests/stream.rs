@@ -0,0 +1,131 @@
+// Make sure that a server can receive messages through a Stream, and that
+// the stream ends once the clients are gone.
+use std::thread;
+use std::time::Duration;
+
+use futures::StreamExt;
+
+use ump::{channel, Error, Incoming};
+
+#[test]
+fn end_when_clients_gone() {
+  let tokrt = tokio::runtime::Runtime::new().unwrap();
+
+  let (server, client) = channel::<u32, u32, ()>();
+
+  let mut client_threads = Vec::new();
+  for i in 0..4 {
+    let client = client.clone();
+    client_threads.push(thread::spawn(move || {
+      for n in 0..10 {
+        assert_eq!(client.send(i * 100 + n).unwrap(), (i * 100 + n) * 2);
+      }
+    }));
+  }
+  drop(client);
+
+  // The stream must not end until every cloned client has been dropped.
+  let count = tokrt.block_on(async {
+    server
+      .into_stream()
+      .fold(0, |count, (n, rctx)| async move {
+        rctx.reply(n * 2).unwrap();
+        count + 1
+      })
+      .await
+  });
+  assert_eq!(count, 40);
+
+  for jh in client_threads {
+    jh.join().unwrap();
+  }
+}
+
+
+#[test]
+fn concurrent_handlers() {
+  let tokrt = tokio::runtime::Runtime::new().unwrap();
+
+  let (server, client) = channel::<u32, u32, ()>();
+
+  let client_thread = thread::spawn(move || {
+    let pending: Vec<_> = (0..8).map(|n| client.post(n).unwrap()).collect();
+    pending
+      .into_iter()
+      .map(|w| w.wait().unwrap())
+      .collect::<Vec<_>>()
+  });
+
+  tokrt.block_on(async {
+    server
+      .into_stream()
+      .for_each_concurrent(4, |(n, rctx)| async move {
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        rctx.reply(n + 1).unwrap();
+      })
+      .await;
+  });
+
+  assert_eq!(client_thread.join().unwrap(), (1..=8).collect::<Vec<_>>());
+}
+
+
+#[test]
+fn end_when_closed() {
+  let tokrt = tokio::runtime::Runtime::new().unwrap();
+
+  let (server, client) = channel::<u32, u32, ()>();
+  let first = client.post(1).unwrap();
+  server.close();
+
+  let stream = server.into_stream();
+  let items = tokrt.block_on(async {
+    stream
+      .map(|(n, rctx)| {
+        rctx.reply(n * 2).unwrap();
+        n
+      })
+      .collect::<Vec<_>>()
+      .await
+  });
+  assert_eq!(items, vec![1]);
+  assert_eq!(first.wait().unwrap(), 2);
+
+  // The stream, and thus the server, is gone.
+  match client.send(2) {
+    Err(Error::ServerDisappeared(n)) => assert_eq!(n, 2),
+    res => panic!("Unexpected result {:?}", res)
+  }
+}
+
+
+#[test]
+fn reply_stream() {
+  let tokrt = tokio::runtime::Runtime::new().unwrap();
+
+  let (server, client) = channel::<u32, u32, ()>();
+
+  let server_thread = thread::spawn(move || {
+    if let Some(Incoming::Stream(n, srctx)) = server.wait_incoming() {
+      for i in 0..n {
+        srctx.send_item(i).unwrap();
+      }
+      srctx.finish().unwrap();
+    }
+  });
+
+  let items = tokrt.block_on(async {
+    client
+      .asend_stream(4)
+      .await
+      .unwrap()
+      .map(Result::unwrap)
+      .collect::<Vec<_>>()
+      .await
+  });
+  assert_eq!(items, vec![0, 1, 2, 3]);
+
+  server_thread.join().unwrap();
+}
+
+// vim: set ft=rust et sw=2 ts=2 sts=2 cinoptions=2 tw=79 :
@@ -0,0 +1,228 @@
+// Make sure that replies can be streamed back to clients.
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+
+use ump::{channel, Cancelled, Error, Incoming, ReplyError};
+
+#[test]
+fn stream_items() {
+  let (server, client) = channel::<u32, u32, String>();
+
+  let server_thread = thread::spawn(move || {
+    while let Some(incoming) = server.wait_incoming() {
+      match incoming {
+        Incoming::Stream(n, srctx) => {
+          for i in 0..n {
+            srctx.send_item(i).unwrap();
+          }
+          if n == 3 {
+            srctx.fail(String::from("Three")).unwrap();
+          } else {
+            srctx.finish().unwrap();
+          }
+        }
+        _ => panic!("Unexpected request")
+      }
+    }
+  });
+
+  let items: Vec<u32> = client
+    .send_stream(5)
+    .unwrap()
+    .into_iter()
+    .map(Result::unwrap)
+    .collect();
+  assert_eq!(items, vec![0, 1, 2, 3, 4]);
+
+  assert_eq!(client.send_stream(0).unwrap().into_iter().count(), 0);
+
+  // Errors end the stream, after the items that were sent before it.
+  let mut stream = client.send_stream(3).unwrap();
+  assert_eq!(stream.wait_next().unwrap().unwrap(), 0);
+  assert_eq!(stream.wait_next().unwrap().unwrap(), 1);
+  assert_eq!(stream.wait_next().unwrap().unwrap(), 2);
+  match stream.wait_next() {
+    Some(Err(Error::App(msg))) => assert_eq!(msg, "Three"),
+    res => panic!("Unexpected result {:?}", res)
+  }
+  assert!(stream.wait_next().is_none());
+
+  drop(client);
+  server_thread.join().unwrap();
+}
+
+
+#[test]
+fn dropped_mid_stream() {
+  let (server, client) = channel::<u32, u32, ()>();
+
+  let server_thread = thread::spawn(move || {
+    if let Some(Incoming::Stream(_, srctx)) = server.wait_incoming() {
+      srctx.send_item(1).unwrap();
+      // Dropped without finishing the stream
+    }
+  });
+
+  let mut stream = client.send_stream(0).unwrap();
+  assert_eq!(stream.wait_next().unwrap().unwrap(), 1);
+  match stream.wait_next() {
+    Some(Err(Error::NoReply)) => {}
+    res => panic!("Unexpected result {:?}", res)
+  }
+  assert!(stream.wait_next().is_none());
+
+  server_thread.join().unwrap();
+}
+
+
+#[test]
+fn client_cancels() {
+  let (server, client) = channel::<u32, u32, ()>();
+
+  let mut stream = client.send_stream(0).unwrap();
+  let Ok(Some(Incoming::Stream(_, srctx))) = server.try_wait_incoming() else {
+    panic!("Expected a stream request");
+  };
+  srctx.send_item(1).unwrap();
+  assert_eq!(stream.wait_next().unwrap().unwrap(), 1);
+  drop(stream);
+
+  assert!(srctx.is_cancelled());
+  match srctx.send_item(2) {
+    Err(ReplyError::Cancelled(n)) => assert_eq!(n, 2),
+    res => panic!("Unexpected result {:?}", res)
+  }
+  assert_eq!(srctx.finish(), Err(Cancelled));
+}
+
+
+#[test]
+fn plain_reply_is_single_item() {
+  let (server, client) = channel::<u32, u32, ()>();
+
+  let stream = client.send_stream(21).unwrap();
+  let (n, rctx) = server.wait().unwrap();
+  rctx.reply(n * 2).unwrap();
+
+  let items: Vec<u32> = stream.into_iter().map(Result::unwrap).collect();
+  assert_eq!(items, vec![42]);
+}
+
+
+#[test]
+fn async_stream() {
+  let tokrt = tokio::runtime::Runtime::new().unwrap();
+
+  let (server, client) = channel::<u32, u32, ()>();
+
+  let server_task = tokrt.spawn(async move {
+    while let Some(incoming) = server.async_wait_incoming().await {
+      if let Incoming::Stream(n, srctx) = incoming {
+        for i in 0..n {
+          srctx.send_item(i * 10).unwrap();
+          tokio::task::yield_now().await;
+        }
+        srctx.finish().unwrap();
+      }
+    }
+  });
+
+  tokrt.block_on(async {
+    let mut stream = client.asend_stream(3).await.unwrap();
+    let mut items = Vec::new();
+    while let Some(item) = stream.async_next().await {
+      items.push(item.unwrap());
+    }
+    assert_eq!(items, vec![0, 10, 20]);
+    drop(client);
+    server_task.await.unwrap();
+  });
+}
+
+
+#[test]
+fn bounded_stream() {
+  let cap = 2;
+  let (server, client) = channel::<usize, usize, ()>();
+  let sent = Arc::new(AtomicUsize::new(0));
+
+  let server_sent = Arc::clone(&sent);
+  let server_thread = thread::spawn(move || {
+    if let Some(Incoming::Stream(n, srctx)) = server.wait_incoming() {
+      for i in 0..n {
+        srctx.send_item(i).unwrap();
+        server_sent.fetch_add(1, Ordering::SeqCst);
+      }
+      srctx.finish().unwrap();
+    }
+  });
+
+  // The server never gets more than `cap` items ahead of the client.
+  let mut stream = client.send_stream_bounded(100, cap).unwrap();
+  let mut taken = 0;
+  while let Some(item) = stream.wait_next() {
+    assert_eq!(item.unwrap(), taken);
+    taken += 1;
+    assert!(sent.load(Ordering::SeqCst) <= taken + cap);
+  }
+  assert_eq!(taken, 100);
+
+  server_thread.join().unwrap();
+}
+
+
+#[test]
+fn bounded_stream_cancelled_while_full() {
+  let (server, client) = channel::<u32, u32, ()>();
+
+  let stream = client.send_stream_bounded(0, 1).unwrap();
+  let Ok(Some(Incoming::Stream(_, srctx))) = server.try_wait_incoming() else {
+    panic!("Expected a stream request");
+  };
+
+  srctx.send_item(1).unwrap();
+
+  let server_thread = thread::spawn(move || {
+    // The buffer is full; blocks until the client gives up.
+    match srctx.send_item(2) {
+      Err(ReplyError::Cancelled(n)) => assert_eq!(n, 2),
+      res => panic!("Unexpected result {:?}", res)
+    }
+  });
+
+  drop(stream);
+  server_thread.join().unwrap();
+}
+
+
+#[test]
+fn async_bounded_stream() {
+  let tokrt = tokio::runtime::Runtime::new().unwrap();
+
+  let (server, client) = channel::<u32, u32, ()>();
+
+  let server_task = tokrt.spawn(async move {
+    while let Some(incoming) = server.async_wait_incoming().await {
+      if let Incoming::Stream(n, srctx) = incoming {
+        for i in 0..n {
+          srctx.async_send_item(i).await.unwrap();
+        }
+        srctx.finish().unwrap();
+      }
+    }
+  });
+
+  tokrt.block_on(async {
+    let mut stream = client.asend_stream_bounded(16, 1).await.unwrap();
+    let mut items = Vec::new();
+    while let Some(item) = stream.async_next().await {
+      items.push(item.unwrap());
+    }
+    assert_eq!(items, (0..16).collect::<Vec<u32>>());
+    drop(client);
+    server_task.await.unwrap();
+  });
+}
+
+// vim: set ft=rust et sw=2 ts=2 sts=2 cinoptions=2 tw=79 :
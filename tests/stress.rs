@@ -9,6 +9,7 @@ enum Ops {
 }
 
 #[test]
+#[allow(clippy::bool_comparison)]
 fn one_at_a_time() {
   let (server, client) = channel::<Ops, i32, ()>();
 
@@ -16,7 +17,7 @@ fn one_at_a_time() {
     let mut croak = false;
 
     while croak == false {
-      let (data, rctx) = server.wait();
+      let (data, rctx) = server.wait().unwrap();
       match data {
         Ops::Die => {
           croak = true;
@@ -58,7 +59,7 @@ fn one_at_a_time_threaded_handler() {
     let mut handles = Vec::new();
     // +1 because we want to wait for the croak message as well
     while count < niterations + 1 {
-      let (data, rctx) = server.wait();
+      let (data, rctx) = server.wait().unwrap();
       let h = thread::spawn(move || match data {
         Ops::Die => {
           rctx.reply(0).unwrap();
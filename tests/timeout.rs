@@ -0,0 +1,132 @@
+// Make sure that send_timeout()/asend_timeout() give up on servers that don't
+// reply in time, and that late replies are discarded.
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+use ump::{channel, Error, ReplyError};
+
+#[test]
+fn sync_expect_timeout() {
+  let (server, client) = channel::<String, String, ()>();
+  let (tx, rx) = mpsc::channel();
+
+  let server_thread = thread::spawn(move || {
+    let (_, rctx) = server.wait().unwrap();
+
+    // Don't reply until the client has given up.
+    rx.recv().unwrap();
+    match rctx.reply(String::from("Too late")) {
+      Err(ReplyError::Cancelled(reply)) => assert_eq!(reply, "Too late"),
+      _ => panic!("Late reply was not returned")
+    }
+
+    // The server must still be usable after a late reply.
+    let (data, rctx) = server.wait().unwrap();
+    rctx.reply(format!("Hello, {}!", data)).unwrap();
+  });
+
+  let msg = String::from("Client");
+  let reply = client.send_timeout(msg, Duration::from_millis(100));
+  match reply {
+    Err(Error::Timeout) => {
+      // This is the expected error
+    }
+    _ => {
+      panic!("Unexpected return value");
+    }
+  }
+  tx.send(()).unwrap();
+
+  let reply = client.send(String::from("Client")).unwrap();
+  assert_eq!(reply, "Hello, Client!");
+
+  server_thread.join().unwrap();
+}
+
+
+#[test]
+fn sync_reply_within_timeout() {
+  let (server, client) = channel::<String, String, ()>();
+
+  let server_thread = thread::spawn(move || {
+    let (data, rctx) = server.wait().unwrap();
+    rctx.reply(format!("Hello, {}!", data)).unwrap();
+  });
+
+  let msg = String::from("Client");
+  let reply = client.send_timeout(msg, Duration::from_secs(10)).unwrap();
+  assert_eq!(reply, "Hello, Client!");
+
+  server_thread.join().unwrap();
+}
+
+
+#[test]
+fn async_expect_timeout() {
+  let tokrt = tokio::runtime::Runtime::new().unwrap();
+
+  let (server, client) = channel::<String, String, ()>();
+  let (tx, rx) = mpsc::channel();
+
+  let server_thread = thread::spawn(move || {
+    let (_, rctx) = server.wait().unwrap();
+
+    rx.recv().unwrap();
+    match rctx.reply(String::from("Too late")) {
+      Err(ReplyError::Cancelled(reply)) => assert_eq!(reply, "Too late"),
+      _ => panic!("Late reply was not returned")
+    }
+  });
+
+  tokrt.block_on(async {
+    let msg = String::from("Client");
+    let reply = client.asend_timeout(msg, Duration::from_millis(100)).await;
+    match reply {
+      Err(Error::Timeout) => {
+        // This is the expected error
+      }
+      _ => {
+        panic!("Unexpected return value");
+      }
+    }
+  });
+  tx.send(()).unwrap();
+
+  server_thread.join().unwrap();
+}
+
+
+#[test]
+fn reply_racing_timeout() {
+  let (server, client) = channel::<u32, u32, ()>();
+
+  // Replies either reach the client, or are handed back to the server;
+  // never both or neither.
+  let server_thread = thread::spawn(move || {
+    let mut delivered = 0;
+    while let Some((n, rctx)) = server.wait() {
+      if rctx.reply(n).is_ok() {
+        delivered += 1;
+      }
+    }
+    delivered
+  });
+
+  let mut received = 0;
+  for n in 0..2000 {
+    match client.send_timeout(n, Duration::from_micros(u64::from(n % 50))) {
+      Ok(reply) => {
+        assert_eq!(reply, n);
+        received += 1;
+      }
+      Err(Error::Timeout) => {}
+      Err(err) => panic!("Unexpected error {:?}", err)
+    }
+  }
+  drop(client);
+
+  assert_eq!(server_thread.join().unwrap(), received);
+}
+
+// vim: set ft=rust et sw=2 ts=2 sts=2 cinoptions=2 tw=79 :
@@ -0,0 +1,47 @@
+// Make sure that pending asend_timeout() futures don't tie up a thread each
+// time they're polled while waiting for their deadlines.  This lives in its
+// own test binary so that no other tests launch threads while they're being
+// counted.
+#![cfg(target_os = "linux")]
+
+use std::fs;
+use std::time::Duration;
+
+use ump::{channel, Error};
+
+fn nthreads() -> usize {
+  fs::read_dir("/proc/self/task").unwrap().count()
+}
+
+
+#[test]
+fn pending_timeouts_share_timer() {
+  let tokrt = tokio::runtime::Builder::new_current_thread()
+    .build()
+    .unwrap();
+
+  // The server never replies, so requests only end by timing out or by being
+  // dropped.
+  let (_server, client) = channel::<u32, u32, ()>();
+
+  tokrt.block_on(async {
+    // Launch the timer before taking the baseline.
+    let reply = client.asend_timeout(0, Duration::from_millis(1)).await;
+    assert!(matches!(reply, Err(Error::Timeout)));
+    let before = nthreads();
+
+    // Poll each request several times; every poll of a request waiting for
+    // its deadline must reuse the timer rather than launch a thread.
+    let mut pending: Vec<_> = (0..64)
+      .map(|n| Box::pin(client.asend_timeout(n, Duration::from_secs(60))))
+      .collect();
+    for _ in 0..8 {
+      for reply in &mut pending {
+        assert!(futures::poll!(reply.as_mut()).is_pending());
+      }
+    }
+    assert_eq!(nthreads(), before);
+  });
+}
+
+// vim: set ft=rust et sw=2 ts=2 sts=2 cinoptions=2 tw=79 :
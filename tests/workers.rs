@@ -0,0 +1,121 @@
+// Make sure that several servers can receive messages from the same clients.
+use std::sync::mpsc;
+use std::thread;
+
+use ump::{channel, Error};
+
+#[test]
+fn spread_across_workers() {
+  let (server, client) = channel::<u32, (usize, u32), ()>();
+  let (picked_tx, picked_rx) = mpsc::channel();
+
+  // Each worker handles a single request, and holds on to it until all the
+  // workers have picked up one.  This can only complete if the requests are
+  // spread across all the workers.
+  let nworkers = 4;
+  let mut workers = Vec::new();
+  let mut go_txs = Vec::new();
+  for id in 0..nworkers {
+    let server = server.clone();
+    let picked_tx = picked_tx.clone();
+    let (tx, rx) = mpsc::channel::<()>();
+    go_txs.push(tx);
+    workers.push(thread::spawn(move || {
+      let (n, rctx) = server.wait().unwrap();
+      picked_tx.send(()).unwrap();
+      rx.recv().unwrap();
+      rctx.reply((id, n * 2)).unwrap();
+    }));
+  }
+  drop(server);
+
+  let mut clients = Vec::new();
+  for n in 0..nworkers as u32 {
+    let client = client.clone();
+    clients.push(thread::spawn(move || client.send(n).unwrap()));
+  }
+
+  for _ in 0..nworkers {
+    picked_rx.recv().unwrap();
+  }
+  for tx in go_txs {
+    tx.send(()).unwrap();
+  }
+
+  let mut ids: Vec<usize> =
+    clients.into_iter().map(|jh| jh.join().unwrap().0).collect();
+  ids.sort();
+  assert_eq!(ids, (0..nworkers).collect::<Vec<_>>());
+
+  for jh in workers {
+    jh.join().unwrap();
+  }
+
+  // All the workers are gone.
+  match client.send(1) {
+    Err(Error::ServerDisappeared(n)) => assert_eq!(n, 1),
+    res => panic!("Unexpected result {:?}", res)
+  }
+}
+
+
+#[test]
+fn disappear_after_last_server() {
+  let (server, client) = channel::<u32, u32, ()>();
+  let server2 = server.clone();
+
+  // The clone keeps the queue alive.
+  drop(server);
+  let server_thread = thread::spawn(move || {
+    let (n, rctx) = server2.wait().unwrap();
+    rctx.reply(n + 1).unwrap();
+  });
+  assert_eq!(client.send(1).unwrap(), 2);
+  server_thread.join().unwrap();
+
+  match client.send(1) {
+    Err(Error::ServerDisappeared(n)) => assert_eq!(n, 1),
+    res => panic!("Unexpected result {:?}", res)
+  }
+}
+
+
+#[test]
+fn mixed_sync_async_workers() {
+  let (server, client) = channel::<u32, u32, ()>();
+
+  let async_server = server.clone();
+  let async_worker = thread::spawn(move || {
+    let tokrt = tokio::runtime::Runtime::new().unwrap();
+    tokrt.block_on(async {
+      for _ in 0..50 {
+        let (n, rctx) = async_server.async_wait().await.unwrap();
+        rctx.reply(n * 2).unwrap();
+      }
+    });
+  });
+  let sync_worker = thread::spawn(move || {
+    for _ in 0..50 {
+      let (n, rctx) = server.wait().unwrap();
+      rctx.reply(n * 2).unwrap();
+    }
+  });
+
+  let mut clients = Vec::new();
+  for i in 0..4 {
+    let client = client.clone();
+    clients.push(thread::spawn(move || {
+      for n in 0..25 {
+        assert_eq!(client.send(i * 100 + n).unwrap(), (i * 100 + n) * 2);
+      }
+    }));
+  }
+  for jh in clients {
+    jh.join().unwrap();
+  }
+
+  async_worker.join().unwrap();
+  sync_worker.join().unwrap();
+}
+
+// vim: set ft=rust et sw=2 ts=2 sts=2 cinoptions=2 tw=79 :